@@ -1,26 +1,35 @@
+use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum B0xxModel {
+    R1,
+    R2,
+}
+
+impl std::fmt::Display for B0xxModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            B0xxModel::R1 => write!(f, "r1"),
+            B0xxModel::R2 => write!(f, "r2"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
 #[repr(u8)]
 pub enum B0xxReport {
     Off = b'0',
     On = b'1',
     End = b'\n',
+    #[default]
     Invalid = 0x00,
 }
 
-impl Into<bool> for B0xxReport {
-    fn into(self) -> bool {
-        match self {
-            B0xxReport::On => true,
-            _ => false,
-        }
-    }
-}
-
-impl Default for B0xxReport {
-    fn default() -> Self {
-        B0xxReport::Invalid
+impl From<B0xxReport> for bool {
+    fn from(value: B0xxReport) -> Self {
+        matches!(value, B0xxReport::On)
     }
 }
 
@@ -59,6 +68,39 @@ pub struct B0xxState {
     pub mod_ms: bool,
 }
 
+impl B0xxState {
+    /// LS/MS only exist on r2 units, an r1 always reports them as released
+    pub fn has_r2_inputs(&self) -> bool {
+        self.mod_ls || self.mod_ms
+    }
+
+    /// Button states in the order of `config::BUTTON_NAMES`
+    pub fn to_array(self) -> [bool; 20] {
+        [
+            self.start,
+            self.y,
+            self.x,
+            self.b,
+            self.a,
+            self.l,
+            self.r,
+            self.z,
+            self.up,
+            self.down,
+            self.right,
+            self.left,
+            self.mod_x,
+            self.mod_y,
+            self.c_left,
+            self.c_right,
+            self.c_up,
+            self.c_down,
+            self.mod_ls,
+            self.mod_ms,
+        ]
+    }
+}
+
 #[cfg(feature = "fake_serial")]
 impl B0xxState {
     pub fn random() -> Self {
@@ -0,0 +1,266 @@
+use crate::b0xx_state::*;
+use crate::error::ViewerError;
+use crate::serial_probe::{parse_report, B0xxMessage};
+use std::io::Write as _;
+
+const CAPTURE_HEADER: &str = "# b0xx_viewer raw capture v1";
+
+pub type SharedCapture = std::sync::Arc<std::sync::Mutex<RawCapture>>;
+
+/// Records every chunk read from the serial port, one line per chunk:
+/// `<microseconds since capture start> <bytes as hex>`. Lines starting with `#` are comments.
+#[derive(Debug)]
+pub struct RawCapture {
+    writer: std::io::BufWriter<std::fs::File>,
+    started: std::time::Instant,
+}
+
+impl RawCapture {
+    pub fn create(path: &std::path::Path) -> Result<Self, ViewerError> {
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+        writeln!(writer, "{}", CAPTURE_HEADER)?;
+        writeln!(
+            writer,
+            "# started {}, b0xx_viewer {}",
+            chrono::Local::now().format("%Y-%m-%dT%H:%M:%S"),
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writer.flush()?;
+
+        Ok(Self {
+            writer,
+            started: std::time::Instant::now(),
+        })
+    }
+
+    pub fn into_shared(self) -> SharedCapture {
+        std::sync::Arc::new(std::sync::Mutex::new(self))
+    }
+
+    #[cfg_attr(feature = "fake_serial", allow(dead_code))]
+    pub fn note(&mut self, note: &str) {
+        let elapsed = self.started.elapsed().as_micros();
+        let res = writeln!(self.writer, "# {} {}", elapsed, note).and_then(|_| self.writer.flush());
+        if let Err(e) = res {
+            warn!("Could not write to raw capture: {}", e);
+        }
+    }
+
+    pub fn record(&mut self, bytes: &[u8]) {
+        let elapsed = self.started.elapsed();
+        // Flush every chunk, the capture is most useful when the viewer misbehaves or crashes
+        let res = write_chunk(&mut self.writer, elapsed, bytes).and_then(|_| self.writer.flush());
+        if let Err(e) = res {
+            warn!("Could not write to raw capture: {}", e);
+        }
+    }
+}
+
+fn write_chunk(
+    writer: &mut impl std::io::Write,
+    elapsed: std::time::Duration,
+    bytes: &[u8],
+) -> std::io::Result<()> {
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    writeln!(writer, "{} {}", elapsed.as_micros(), hex)
+}
+
+pub fn record(capture: &Option<SharedCapture>, bytes: &[u8]) {
+    if let Some(capture) = capture {
+        if let Ok(mut capture) = capture.lock() {
+            capture.record(bytes);
+        }
+    }
+}
+
+#[cfg_attr(feature = "fake_serial", allow(dead_code))]
+pub fn note(capture: &Option<SharedCapture>, note: &str) {
+    if let Some(capture) = capture {
+        if let Ok(mut capture) = capture.lock() {
+            capture.note(note);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CapturedChunk {
+    elapsed: std::time::Duration,
+    bytes: Vec<u8>,
+}
+
+fn load_capture(path: &std::path::Path) -> Result<Vec<CapturedChunk>, ViewerError> {
+    read_capture(std::io::BufReader::new(std::fs::File::open(path)?))
+}
+
+fn read_capture(reader: impl std::io::BufRead) -> Result<Vec<CapturedChunk>, ViewerError> {
+    let mut chunks = vec![];
+
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let malformed = || ViewerError::MalformedCapture(line_no + 1);
+        // Empty chunks are written without a payload
+        let (elapsed, hex) = line.split_once(' ').unwrap_or((line, ""));
+        let elapsed: u64 = elapsed.parse().map_err(|_| malformed())?;
+        if hex.len() % 2 != 0 {
+            return Err(malformed());
+        }
+
+        let bytes = hex
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| {
+                std::str::from_utf8(pair)
+                    .ok()
+                    .filter(|pair| pair.bytes().all(|b| b.is_ascii_hexdigit()))
+                    .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(malformed)?;
+
+        chunks.push(CapturedChunk {
+            elapsed: std::time::Duration::from_micros(elapsed),
+            bytes,
+        });
+    }
+
+    Ok(chunks)
+}
+
+/// Feeds a raw capture through the report parser with its original timing, looping at the end
+pub fn start_replay(
+    path: &std::path::Path,
+) -> Result<crossbeam_channel::Receiver<B0xxMessage>, ViewerError> {
+    let chunks = load_capture(path)?;
+    let malformed = chunks
+        .iter()
+        .filter(|chunk| chunk.bytes.len() != 25)
+        .count();
+    info!(
+        "Replaying {} chunks from {:?} ({} partial or malformed)",
+        chunks.len(),
+        path,
+        malformed
+    );
+
+    if chunks.is_empty() {
+        return Err(ViewerError::MalformedCapture(0));
+    }
+
+    let (tx, rx) = crossbeam_channel::bounded(1);
+
+    std::thread::Builder::new()
+        .name("b0xx_viewer_replay".into())
+        .spawn(move || loop {
+            let started = std::time::Instant::now();
+            let mut state = [B0xxReport::default(); 20];
+            let mut previous = B0xxState::default();
+
+            for chunk in &chunks {
+                if let Some(wait) = chunk.elapsed.checked_sub(started.elapsed()) {
+                    std::thread::sleep(wait);
+                }
+
+                if !parse_report(&chunk.bytes, &mut state) {
+                    debug!(
+                        "[{:?}] partial report of {} bytes: {:?}",
+                        chunk.elapsed,
+                        chunk.bytes.len(),
+                        String::from_utf8_lossy(&chunk.bytes)
+                    );
+                    continue;
+                }
+
+                let new_state: B0xxState = state.into();
+                if new_state != previous {
+                    debug!("[{:?}] {:?}", chunk.elapsed, new_state);
+                    previous = new_state;
+                }
+
+                if tx.send(B0xxMessage::State(new_state)).is_err() {
+                    return;
+                }
+            }
+
+            info!("Replay finished, starting over");
+        })?;
+
+    Ok(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn report(pressed: &[usize]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = (0..24)
+            .map(|i| if pressed.contains(&i) { b'1' } else { b'0' })
+            .collect();
+        bytes.push(b'\n');
+        bytes
+    }
+
+    #[test]
+    fn captures_replay_into_states() {
+        let mut capture = vec![];
+        writeln!(capture, "{}", CAPTURE_HEADER).unwrap();
+        write_chunk(&mut capture, Duration::from_micros(0), &report(&[])).unwrap();
+        writeln!(capture, "# 1000 port reopened").unwrap();
+        write_chunk(&mut capture, Duration::from_micros(16670), b"0101").unwrap();
+        write_chunk(&mut capture, Duration::from_micros(16700), &[]).unwrap();
+        write_chunk(
+            &mut capture,
+            Duration::from_micros(33340),
+            &report(&[0, 4, 19]),
+        )
+        .unwrap();
+
+        let chunks = read_capture(&capture[..]).unwrap();
+        assert_eq!(
+            chunks.iter().map(|chunk| chunk.elapsed).collect::<Vec<_>>(),
+            [0, 16670, 16700, 33340].map(Duration::from_micros)
+        );
+        assert_eq!(chunks[1].bytes, b"0101");
+        assert!(chunks[2].bytes.is_empty());
+
+        let mut state = [B0xxReport::default(); 20];
+        let mut states = vec![];
+        for chunk in &chunks {
+            if parse_report(&chunk.bytes, &mut state) {
+                states.push(B0xxState::from(state));
+            }
+        }
+        assert_eq!(
+            states,
+            [
+                B0xxState::default(),
+                B0xxState {
+                    start: true,
+                    a: true,
+                    mod_ms: true,
+                    ..Default::default()
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_captures_are_rejected() {
+        let line_of = |capture: &str| match read_capture(capture.as_bytes()) {
+            Err(ViewerError::MalformedCapture(line)) => line,
+            other => panic!("expected a malformed capture, got {:?}", other),
+        };
+        assert_eq!(line_of("# comment\n0 3031\nlater 3031"), 3);
+        assert_eq!(line_of("0 303"), 1);
+        assert_eq!(line_of("0 zz"), 1);
+        assert_eq!(line_of("0 aéb"), 1);
+        assert_eq!(line_of("0 éé"), 1);
+        assert_eq!(line_of("0 +f"), 1);
+        assert!(read_capture(&b""[..]).unwrap().is_empty());
+    }
+}
@@ -1,107 +1,849 @@
 use crate::config::*;
-use crate::hex_to_color;
 use clap::{clap_app, crate_authors, crate_description, crate_version};
 
-pub fn cli_options() -> ViewerOptions {
-    let matches = clap_app!(b0xx_viewer =>
+#[derive(Debug, thiserror::Error)]
+pub enum CliError {
+    #[error(transparent)]
+    Clap(#[from] clap::Error),
+    #[error("Invalid value for '{flag}': {message}")]
+    InvalidValue { flag: String, message: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigCommand {
+    Validate(Option<std::path::PathBuf>),
+    Show { sources: bool },
+    Diff,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ThemeCommand {
+    List,
+    Export,
+    Import(String),
+}
+
+/// What the viewer was asked to do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliCommand {
+    Run,
+    InitConfig,
+    PrintUdevRule,
+    Config(ConfigCommand),
+    Theme(ThemeCommand),
+}
+
+/// The parsed command line. Nothing is read from the system while parsing:
+/// files, environment variables and serial ports are looked at in [`load_config`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CliArgs {
+    pub command: CliCommand,
+    pub config_path: Option<std::path::PathBuf>,
+    /// The command line configuration layer, applied on top of files and environment variables
+    pub overrides: ConfigLayer,
+    pub tty: Option<String>,
+    pub capture_raw: Option<std::path::PathBuf>,
+    pub replay_raw: Option<std::path::PathBuf>,
+}
+
+/// Splits a `button=value` override, eg. `a=#29A9A1`
+fn button_override(value: &str) -> Result<(&str, &str), String> {
+    let (button, value) = value
+        .split_once('=')
+        .ok_or_else(|| format!("Expected <button>=<value>, got \"{}\"", value))?;
+
+    let button = button.trim();
+    if !BUTTON_NAMES.contains(&button) {
+        return Err(format!(
+            "Unknown button \"{}\", expected one of: {}",
+            button,
+            BUTTON_NAMES.join(", ")
+        ));
+    }
+
+    Ok((button, value))
+}
+
+fn validate_color(value: String) -> Result<(), String> {
+    value.parse::<ViewerColor>().map(|_| ())
+}
+
+fn validate_button_color(value: String) -> Result<(), String> {
+    let (_, color) = button_override(&value)?;
+    color.parse::<ViewerColor>().map(|_| ())
+}
+
+fn validate_scale(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(scale) if (MIN_SCALE..=MAX_SCALE).contains(&scale) => Ok(()),
+        _ => Err(format!(
+            "expected a number between {} and {}, eg. 2 for a 1200x600 window",
+            MIN_SCALE, MAX_SCALE
+        )),
+    }
+}
+
+fn validate_theme(value: String) -> Result<(), String> {
+    if value.is_empty() || bundled_theme_names().any(|name| name == value) {
+        return Ok(());
+    }
+
+    Err(format!(
+        "unknown theme, available themes: {}",
+        bundled_theme_names().collect::<Vec<_>>().join(", ")
+    ))
+}
+
+fn validate_button_label(value: String) -> Result<(), String> {
+    button_override(&value).map(|_| ())
+}
+
+fn app() -> clap::App<'static, 'static> {
+    clap_app!(b0xx_viewer =>
         (version: crate_version!())
         (author: crate_authors!())
         (about: crate_description!())
         (@arg labels: -l --labels "Enable button labels")
-        (@arg bg_color: -b --background +takes_value "Sets a custom background color in hex format, eg. \"#00FF00\"")
-        (@arg btn_inactive_color: -i --inactive +takes_value "Sets a custom color for inactive buttons in hex format, eg. \"#00FF00\"")
-        (@arg btn_active_color: -a --active +takes_value "Sets a custom color for pressed/active buttons in hex format, eg. \"#00FF00\"")
-        (@arg init_config: --init_config "Intializes an empty configuration in the executable's folder")
+        (@arg bg_color: -b --background +takes_value {validate_color} "Sets a custom background color in hex format, eg. \"#00FF00\", \"#00FF0080\" or a color name")
+        (@arg btn_inactive_color: -i --inactive +takes_value {validate_color} "Sets a custom color for inactive buttons in hex format, eg. \"#00FF00\", \"#00FF0080\" or a color name")
+        (@arg btn_active_color: -a --active +takes_value {validate_color} "Sets a custom color for pressed/active buttons in hex format, eg. \"#00FF00\", \"#00FF0080\" or a color name")
+        (@arg btn_inactive_overrides: --("inactive-color") +takes_value +multiple number_of_values(1) {validate_button_color} "Sets the color of a single inactive button, eg. \"a=#29A9A1\". Can be repeated")
+        (@arg btn_active_overrides: --("active-color") +takes_value +multiple number_of_values(1) {validate_button_color} "Sets the color of a single pressed/active button, eg. \"a=#29C7BE\". Can be repeated")
+        (@arg label_overrides: --label +takes_value +multiple number_of_values(1) {validate_button_label} "Sets the label of a single button, eg. \"mod_x=MX1\". Can be repeated")
+        (@arg label_position: --("label-position") +takes_value possible_value[inside above below hidden] "Places the labels inside, above or below the buttons, or hides them")
+        (@arg button_shape: --("button-shape") +takes_value possible_value[circle square ellipse rounded_rectangle] "Draws the buttons as circles, squares, ellipses or rounded rectangles")
+        (@arg init_config: --init_config "Intializes an empty configuration in the user configuration folder")
         (@arg config: -c --config +takes_value "Sets the configuration file path")
         (@arg chromeless: --chromeless "Makes the window chromeless")
+        (@arg transparent: --transparent "Only draws the buttons over a transparent window background, for captures that support alpha")
+        (@arg scale: --scale +takes_value {validate_scale} "Opens the window at a multiple of its base 600x300 size, eg. 2 or 1.5. The window can also be resized freely")
+        (@arg stretch: --stretch "Stretches the layout to fill the window instead of keeping its proportions")
+        (@arg theme: --theme +takes_value {validate_theme} "Applies a bundled theme over the configuration file, other command line options still apply on top of it. See `theme list`")
+        (@arg profile: --profile +takes_value "Applies a profile from the configuration file. Profiles can be switched while running with Tab or the 0-9 keys")
         (@arg tty: --tty +takes_value "Provide a custom COM port (Windows-only) or a /dev/ttyXXX path (Unix). Bypasses auto-detection, so proceed at your own risk!")
         (@arg relax_arduino: --relax_arduino_detection "Relaxes B0XX detection to allow any 16MHz Arduino-compatible device to connect")
-        (@arg r2: --r2 "Enables B0XX r2 mode to account for the 2 extra buttons")
+        (@arg r2: --r2 conflicts_with[model] "Enables B0XX r2 mode to account for the 2 extra buttons. Same as --model r2")
+        (@arg model: --model +takes_value possible_value[auto r1 r2] "Selects the B0XX model layout. \"auto\" detects r2 units from their reports or a configured USB fingerprint")
+        (@arg capture_raw: --("capture-raw") +takes_value "Dumps every byte read from the serial port, with timestamps, to the given file. Useful for bug reports")
+        (@arg replay_raw: --("replay-raw") +takes_value conflicts_with[capture_raw tty] "Replays a file recorded with --capture-raw instead of connecting to a B0XX")
+        (@arg single_instance: --single_instance "Refuses to start if another viewer is already connected to the same device")
+        (@arg print_udev_rule: --("print-udev-rule") "Prints a udev rule granting access to the detected B0XX, then exits (Linux)")
         (@arg colored_rims: --colored_rims "Enables an alternative mode of inactive button coloring; Makes inactive button background neutral in favor of button rims instead.")
+        (@subcommand config =>
+            (about: "Inspects configuration files")
+            (@setting SubcommandRequiredElseHelp)
+            (@subcommand validate =>
+                (about: "Checks a configuration file and reports errors with their line and column")
+                (@arg file: "The file to check, defaults to the configuration that would be loaded"))
+            (@subcommand show =>
+                (about: "Prints the effective configuration, including environment and command line overrides")
+                (@arg sources: --sources "Prints where every value comes from instead"))
+            (@subcommand diff =>
+                (about: "Prints the effective values that differ from the built-in defaults"))
+        )
+        (@subcommand theme =>
+            (about: "Lists the bundled themes, shares colors, labels and layout as short codes")
+            (@setting SubcommandRequiredElseHelp)
+            (@subcommand list =>
+                (about: "Lists the bundled themes usable with --theme"))
+            (@subcommand export =>
+                (about: "Prints the theme of the effective configuration as a code"))
+            (@subcommand import =>
+                (about: "Applies a theme code to the configuration file, keeping its other settings")
+                (@arg code: +required "The code printed by `theme export`"))
+        )
     )
-    .get_matches();
+}
+
+fn parse_color(matches: &clap::ArgMatches, arg: &str, flag: &str) -> Result<Option<ViewerColor>, CliError> {
+    matches
+        .value_of(arg)
+        .map(|value| {
+            value.parse().map_err(|message| CliError::InvalidValue {
+                flag: flag.into(),
+                message,
+            })
+        })
+        .transpose()
+}
+
+fn overrides_layer(matches: &clap::ArgMatches) -> Result<ConfigLayer, CliError> {
+    let mut layer = ConfigLayer::empty();
+
+    let flags = [
+        ("labels", "--labels", "display_labels"),
+        ("chromeless", "--chromeless", "chromeless"),
+        ("transparent", "--transparent", "transparent"),
+        ("relax_arduino", "--relax_arduino_detection", "detection.relax_arduino"),
+        ("single_instance", "--single_instance", "single_instance"),
+        ("colored_rims", "--colored_rims", "colored_rims"),
+    ];
+
+    for (arg, flag, key) in flags.iter() {
+        if matches.is_present(arg) {
+            layer.set(key, toml::Value::Boolean(true), ConfigSource::Cli(flag.to_string()));
+        }
+    }
+
+    if matches.is_present("stretch") {
+        layer.set(
+            "keep_aspect_ratio",
+            toml::Value::Boolean(false),
+            ConfigSource::Cli("--stretch".into()),
+        );
+    }
+
+    if let Some(scale) = matches.value_of("scale") {
+        // Already checked by validate_scale
+        let scale = scale.parse().unwrap_or(1.);
+        layer.set("scale", toml::Value::Float(scale), ConfigSource::Cli("--scale".into()));
+    }
+
+    if let Some(model) = matches.value_of("model") {
+        layer.set(
+            "model",
+            toml::Value::String(model.into()),
+            ConfigSource::Cli("--model".into()),
+        );
+    } else if matches.is_present("r2") {
+        layer.set(
+            "model",
+            toml::Value::String("r2".into()),
+            ConfigSource::Cli("--r2".into()),
+        );
+    }
+
+    if let Some(position) = matches.value_of("label_position") {
+        layer.set(
+            "label_position",
+            toml::Value::String(position.into()),
+            ConfigSource::Cli("--label-position".into()),
+        );
+    }
+
+    if let Some(shape) = matches.value_of("button_shape") {
+        layer.set(
+            "button_shape",
+            toml::Value::String(shape.into()),
+            ConfigSource::Cli("--button-shape".into()),
+        );
+    }
 
-    if matches.is_present("init_config") {
-        let mut ret = ViewerOptions::default();
-        let _ = ret.save_cwd().unwrap();
-        info!("configuration saved in ./b0xx_viewer_config.toml");
-        std::process::exit(0);
+    if let Some(theme) = matches.value_of("theme") {
+        layer.set(
+            "theme",
+            toml::Value::String(theme.into()),
+            ConfigSource::Cli("--theme".into()),
+        );
+    }
+
+    if let Some(profile) = matches.value_of("profile") {
+        layer.set(
+            "profile",
+            toml::Value::String(profile.into()),
+            ConfigSource::Cli("--profile".into()),
+        );
+    }
+
+    if let Some(color) = parse_color(matches, "bg_color", "--background")? {
+        layer.set(
+            "background_color",
+            toml::Value::String(color.to_hex()),
+            ConfigSource::Cli("--background".into()),
+        );
+    }
+
+    let colors = [
+        ("btn_inactive_color", "--inactive", "button_inactive_colors"),
+        ("btn_active_color", "--active", "button_active_colors"),
+    ];
+
+    for (arg, flag, key) in colors.iter() {
+        if let Some(color) = parse_color(matches, arg, flag)? {
+            for button in BUTTON_NAMES.iter() {
+                layer.set(
+                    &format!("{}.{}", key, button),
+                    toml::Value::String(color.to_hex()),
+                    ConfigSource::Cli(flag.to_string()),
+                );
+            }
+        }
     }
 
-    if matches.is_present("relax_arduino") {
-        std::env::set_var("RELAX_ARDUINO_DETECT", "true");
+    // Single button overrides are applied on top of the colors set for every button
+    let overrides = [
+        ("btn_active_overrides", "--active-color", "button_active_colors"),
+        ("btn_inactive_overrides", "--inactive-color", "button_inactive_colors"),
+        ("label_overrides", "--label", "labels"),
+    ];
+
+    for (arg, flag, key) in overrides.iter() {
+        for value in matches.values_of(arg).into_iter().flatten() {
+            let (button, value) = button_override(value).map_err(|message| CliError::InvalidValue {
+                flag: flag.to_string(),
+                message,
+            })?;
+
+            layer.set(
+                &format!("{}.{}", key, button),
+                toml::Value::String(value.into()),
+                ConfigSource::Cli(format!("{} {}={}", flag, button, value)),
+            );
+        }
     }
 
-    let mut ret = if let Some(config_path) = matches.value_of("config").take() {
-        info!("Loading custom config at {}", config_path);
-        match ViewerOptions::load(config_path.into()) {
-            Ok(config) => config,
+    Ok(layer)
+}
+
+/// Parses the command line without side effects. `--help` and `--version` are reported
+/// as [`clap::Error`]s, as is any invalid argument
+pub fn parse_args<I, T>(args: I) -> Result<CliArgs, CliError>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    let matches = app().get_matches_from_safe(args)?;
+
+    let command = if let Some(config) = matches.subcommand_matches("config") {
+        CliCommand::Config(match config.subcommand() {
+            ("validate", Some(validate)) => {
+                ConfigCommand::Validate(validate.value_of("file").map(Into::into))
+            }
+            ("show", Some(show)) => ConfigCommand::Show {
+                sources: show.is_present("sources"),
+            },
+            _ => ConfigCommand::Diff,
+        })
+    } else if let Some(theme) = matches.subcommand_matches("theme") {
+        CliCommand::Theme(match theme.subcommand() {
+            ("import", Some(import)) => {
+                ThemeCommand::Import(import.value_of("code").unwrap_or_default().into())
+            }
+            ("list", _) => ThemeCommand::List,
+            _ => ThemeCommand::Export,
+        })
+    } else if matches.is_present("init_config") {
+        CliCommand::InitConfig
+    } else if matches.is_present("print_udev_rule") {
+        CliCommand::PrintUdevRule
+    } else {
+        CliCommand::Run
+    };
+
+    Ok(CliArgs {
+        command,
+        config_path: matches.value_of("config").map(Into::into),
+        overrides: overrides_layer(&matches)?,
+        tty: matches.value_of("tty").map(Into::into),
+        capture_raw: matches.value_of("capture_raw").map(Into::into),
+        replay_raw: matches.value_of("replay_raw").map(Into::into),
+    })
+}
+
+impl CliArgs {
+    /// The configuration file asked for with `-c` or `B0XX_VIEWER_CONFIG_PATH`
+    fn explicit_config_path(&self) -> Option<std::path::PathBuf> {
+        self.config_path
+            .clone()
+            .or_else(ViewerOptions::config_path_override)
+    }
+
+    fn find_config_path(&self) -> Option<std::path::PathBuf> {
+        self.explicit_config_path()
+            .or_else(ViewerOptions::find_config)
+    }
+}
+
+/// Builds the configuration layers from the config file, the environment and the command line
+pub fn load_config(args: &CliArgs, migration: FileMigration) -> LayeredConfig {
+    let mut config = LayeredConfig::default();
+
+    if let Some(config_path) = args.explicit_config_path() {
+        info!("Loading custom config at {}", config_path.display());
+    }
+
+    if let Some(config_path) = args.find_config_path() {
+        debug!("Using configuration file {}", config_path.display());
+        match ConfigLayer::from_file(&config_path, migration) {
+            Ok(layer) => config.file = Some((config_path, layer)),
             Err(e) => {
                 error!("{}", e);
                 error!("Falling back to default configuration");
-                ViewerOptions::default()
             }
         }
     } else {
-        ViewerOptions::load_cwd().unwrap_or_default()
-    };
+        debug!(
+            "No configuration file found, searched {:?}",
+            ViewerOptions::search_paths()
+        );
+    }
+
+    config.env = ConfigLayer::from_env();
+    config.cli = args.overrides.clone();
 
-    if matches.is_present("labels") {
-        ret.display_labels = true;
+    if let Some(tty) = &args.tty {
+        match serialport::available_ports() {
+            Ok(ports) if ports.iter().any(|p| &p.port_name == tty) => config.cli.set(
+                "custom_tty",
+                toml::Value::String(tty.clone()),
+                ConfigSource::Cli("--tty".into()),
+            ),
+            Ok(_) => error!("Provided port not found or not connected to system"),
+            Err(_) => error!("No ports available on the system, cannot lookup"),
+        }
     }
 
-    if matches.is_present("chromeless") {
-        ret.chromeless = true;
+    if let Some(name) = config.unknown_profile() {
+        warn!("Unknown profile `{}`, ignoring it", name);
     }
 
-    if matches.is_present("r2") {
-        ret.is_r2_b0xx = true;
+    config
+}
+
+/// Resolves the options to start the viewer with, falling back to the defaults and the command
+/// line alone if the configuration can't be used
+pub fn resolve_config(config: &mut LayeredConfig) -> ViewerOptions {
+    let options = match config.resolve() {
+        Ok(resolved) => {
+            for line in resolved.explain() {
+                debug!("{}", line);
+            }
+            for warning in theme_warnings(&resolved.options) {
+                warn!("Theme: {}", warning);
+            }
+            resolved.options
+        }
+        Err(e) => {
+            error!("{}", e);
+            error!("Falling back to default configuration");
+            *config = LayeredConfig {
+                cli: config.cli.clone(),
+                ..Default::default()
+            };
+            config
+                .resolve()
+                .map(|resolved| resolved.options)
+                .unwrap_or_default()
+        }
+    };
+
+    trace!("Configuration: {:#?}", options);
+    options
+}
+
+/// Writes the default configuration to the user config directory, returns the process exit code
+pub fn init_config() -> i32 {
+    if let Some(existing) = ViewerOptions::user_config_path().filter(|path| path.exists()) {
+        error!(
+            "A configuration already exists at {}, remove it first to start over",
+            existing.display()
+        );
+        return 1;
     }
 
-    if matches.is_present("colored_rims") {
-        ret.colored_rims = true;
+    let mut options = ViewerOptions::default();
+    match options.save_user_config() {
+        Ok(()) => {
+            info!("configuration saved in {}", options.path().display());
+            0
+        }
+        Err(e) => {
+            error!("{}", e);
+            1
+        }
     }
+}
 
-    if let Some(tty) = matches.value_of("tty").take() {
-        if let Ok(ports) = serialport::available_ports() {
-            ret.custom_tty = ports
-                .into_iter()
-                .find(|p| p.port_name == tty)
-                .map(move |_| String::from(tty));
+/// Runs `config validate|show|diff`, returns the process exit code
+pub fn run_config_command(args: &CliArgs, command: &ConfigCommand) -> i32 {
+    if let ConfigCommand::Validate(file) = command {
+        let path = match file.clone().or_else(|| args.find_config_path()) {
+            Some(path) => path,
+            None => {
+                eprintln!("error: no configuration file found");
+                return 1;
+            }
+        };
 
-            if ret.custom_tty.is_none() {
-                error!("Provided port not found or not connected to system");
+        return match validate_file(&path) {
+            Ok(warnings) => {
+                for warning in &warnings {
+                    eprintln!("{}: warning: {}", path.display(), warning);
+                }
+                println!("{}: OK", path.display());
+                0
             }
-        } else {
-            error!("No ports available on the system, cannot lookup");
+            Err(e) => {
+                eprintln!("{}: error: {}", path.display(), e);
+                1
+            }
+        };
+    }
+
+    let resolved = match load_config(args, FileMigration::InMemory).resolve() {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return 1;
+        }
+    };
+
+    let output = match command {
+        ConfigCommand::Show { sources: true } => Ok(resolved.explain().join("\n")),
+        ConfigCommand::Show { sources: false } => resolved.to_toml(),
+        ConfigCommand::Diff => resolved.diff_from_defaults().map(|lines| lines.join("\n")),
+        ConfigCommand::Validate(_) => unreachable!(),
+    };
+
+    match output {
+        Ok(output) => {
+            println!("{}", output);
+            0
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            1
         }
     }
+}
 
-    if let Some(Ok(bg)) = matches
-        .value_of("bg_color")
-        .take()
-        .map(|s| u32::from_str_radix(s.trim_start_matches('#'), 16))
+/// Runs `theme list|export|import`, returns the process exit code
+pub fn run_theme_command(args: &CliArgs, command: &ThemeCommand) -> i32 {
+    let code = match command {
+        ThemeCommand::List => {
+            for name in bundled_theme_names() {
+                println!("{}", name);
+            }
+            return 0;
+        }
+        ThemeCommand::Export => {
+            return match load_config(args, FileMigration::InMemory).resolve() {
+                Ok(resolved) => {
+                    println!("{}", ViewerTheme::from(&resolved.options).to_code());
+                    0
+                }
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    1
+                }
+            };
+        }
+        ThemeCommand::Import(code) => code,
+    };
+
+    let path = match args
+        .find_config_path()
+        .or_else(ViewerOptions::user_config_path)
     {
-        ret.background_color = hex_to_color!(bg);
+        Some(path) => path,
+        None => {
+            eprintln!("error: no configuration directory found, use -c to pick a file");
+            return 1;
+        }
+    };
+
+    match import_theme(&path, code) {
+        Ok(()) => {
+            println!("Theme applied to {}", path.display());
+            0
+        }
+        Err(e) => {
+            eprintln!("{}: error: {}", path.display(), e);
+            1
+        }
     }
+}
 
-    if let Some(Ok(bg)) = matches
-        .value_of("btn_inactive_color")
-        .take()
-        .map(|s| u32::from_str_radix(s.trim_start_matches('#'), 16))
-    {
-        ret.button_inactive_colors = ViewerButtonColors::new_with_color(hex_to_color!(bg));
+fn import_theme(path: &std::path::Path, code: &str) -> Result<(), crate::error::ViewerError> {
+    let theme = ViewerTheme::from_code(code)?;
+
+    let mut values = if path.exists() {
+        let mut values: toml::value::Table = toml::from_str(&std::fs::read_to_string(path)?)
+            .map_err(ConfigError::DeserializationError)?;
+        migrate_file(path, &mut values, FileMigration::Rewrite)?;
+        values
+    } else {
+        let mut values = toml::value::Table::new();
+        values.insert("version".into(), toml::Value::Integer(CONFIG_VERSION as i64));
+        values
+    };
+    theme.apply_to(&mut values)?;
+
+    let buf = toml::ser::to_vec(&toml::Value::Table(values))
+        .map_err(ConfigError::SerializationError)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
     }
+    std::fs::write(path, buf)?;
+    Ok(())
+}
 
-    if let Some(Ok(bg)) = matches
-        .value_of("btn_active_color")
-        .take()
-        .map(|s| u32::from_str_radix(s.trim_start_matches('#'), 16))
-    {
-        ret.button_active_colors = ViewerButtonColors::new_with_color(hex_to_color!(bg));
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<CliArgs, CliError> {
+        parse_args(std::iter::once("b0xx_viewer").chain(args.iter().copied()))
+    }
+
+    fn options(args: &[&str]) -> ViewerOptions {
+        LayeredConfig {
+            cli: parse(args).unwrap().overrides,
+            ..Default::default()
+        }
+        .resolve()
+        .unwrap()
+        .options
+    }
+
+    #[test]
+    fn no_arguments_runs_with_defaults() {
+        let args = parse(&[]).unwrap();
+        assert_eq!(args.command, CliCommand::Run);
+        assert_eq!(args.config_path, None);
+        assert_eq!(options(&[]), ViewerOptions::default());
     }
 
-    trace!("Configuration: {:#?}", ret);
+    #[test]
+    fn flags_set_options() {
+        let options = options(&[
+            "-l",
+            "--chromeless",
+            "--transparent",
+            "--relax_arduino_detection",
+            "--colored_rims",
+        ]);
+        assert!(options.display_labels);
+        assert!(options.chromeless);
+        assert!(options.transparent);
+        assert!(options.detection.relax_arduino);
+        assert!(options.colored_rims);
+    }
+
+    #[test]
+    fn scale_flags() {
+        assert_eq!(options(&["--scale", "2.5"]).scale, 2.5);
+        assert!(!options(&["--stretch"]).keep_aspect_ratio);
+        assert!(parse(&["--scale", "0"]).is_err());
+        assert!(parse(&["--scale", "big"]).is_err());
+    }
+
+    #[test]
+    fn r2_flag_selects_the_r2_model() {
+        assert_eq!(options(&["--r2"]).model, ViewerModelSelection::R2);
+        assert_eq!(options(&["--model", "r1"]).model, ViewerModelSelection::R1);
+        assert!(parse(&["--r2", "--model", "r1"]).is_err());
+        assert!(parse(&["--model", "r3"]).is_err());
+    }
+
+    #[test]
+    fn colors_are_parsed() {
+        let options = options(&["-b", "#010203", "-a", "red", "-i", "#00FF0080"]);
+        assert_eq!(options.background_color, "#010203".parse().unwrap());
+        assert_eq!(options.button_active_colors, ViewerButtonColors::new_with_color("red".parse().unwrap()));
+        assert_eq!(options.button_inactive_colors.a, "#00FF0080".parse().unwrap());
+    }
+
+    #[test]
+    fn invalid_colors_are_reported() {
+        assert!(parse(&["-b", "#12345"]).is_err());
+        assert!(parse(&["-a", "not-a-color"]).is_err());
+        assert!(parse(&["--active-color", "a=#GGGGGG"]).is_err());
+    }
+
+    #[test]
+    fn button_overrides_apply_on_top_of_every_button_colors() {
+        let options = options(&["-a", "blue", "--active-color", "a=red", "--active-color", "mod_x=#112233"]);
+        assert_eq!(options.button_active_colors.a, "red".parse().unwrap());
+        assert_eq!(options.button_active_colors.mod_x, "#112233".parse().unwrap());
+        assert_eq!(options.button_active_colors.b, "blue".parse().unwrap());
+    }
 
-    ret
+    #[test]
+    fn labels_are_overridden() {
+        let options = options(&["--label", "mod_x=MX1", "--label", "start="]);
+        assert_eq!(options.labels.mod_x, "MX1");
+        assert_eq!(options.labels.start, "");
+        assert_eq!(options.labels.a, ViewerButtonLabels::default().a);
+    }
+
+    #[test]
+    fn label_position_flag() {
+        assert_eq!(options(&["--label-position", "below"]).label_position, LabelPosition::Below);
+        assert!(parse(&["--label-position", "left"]).is_err());
+    }
+
+    #[test]
+    fn button_shape_flag() {
+        let options = options(&["--button-shape", "rounded_rectangle"]);
+        assert_eq!(options.button_shape.kind, ShapeKind::RoundedRectangle);
+        assert!(parse(&["--button-shape", "polygon"]).is_err());
+    }
+
+    #[test]
+    fn themes_are_overridden_by_other_options() {
+        for name in bundled_theme_names() {
+            assert!(bundled_theme(name).is_some(), "{}", name);
+            assert_eq!(options(&["--theme", name]).theme.as_deref(), Some(name));
+        }
+
+        let options = options(&["--theme", "gcc", "--active-color", "a=red"]);
+        assert_eq!(options.button_active_colors.a, "red".parse().unwrap());
+        assert_eq!(options.button_active_colors.b, "#FF3148".parse().unwrap());
+        assert!(options.display_labels);
+        assert!(parse(&["--theme", "foo"]).is_err());
+    }
+
+    #[test]
+    fn unknown_buttons_are_reported() {
+        assert!(parse(&["--label", "foo=bar"]).is_err());
+        assert!(parse(&["--inactive-color", "foo=red"]).is_err());
+        assert!(parse(&["--label", "mod_x"]).is_err());
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(parse(&["--init_config"]).unwrap().command, CliCommand::InitConfig);
+        assert_eq!(parse(&["--print-udev-rule"]).unwrap().command, CliCommand::PrintUdevRule);
+        assert_eq!(
+            parse(&["config", "validate", "my.toml"]).unwrap().command,
+            CliCommand::Config(ConfigCommand::Validate(Some("my.toml".into())))
+        );
+        assert_eq!(
+            parse(&["-l", "config", "show", "--sources"]).unwrap().command,
+            CliCommand::Config(ConfigCommand::Show { sources: true })
+        );
+        assert_eq!(
+            parse(&["config", "diff"]).unwrap().command,
+            CliCommand::Config(ConfigCommand::Diff)
+        );
+        assert!(parse(&["config"]).is_err());
+        assert_eq!(
+            parse(&["theme", "export"]).unwrap().command,
+            CliCommand::Theme(ThemeCommand::Export)
+        );
+        assert_eq!(
+            parse(&["-c", "my.toml", "theme", "import", "b0xx-theme1:AQ"]).unwrap().command,
+            CliCommand::Theme(ThemeCommand::Import("b0xx-theme1:AQ".into()))
+        );
+        assert!(parse(&["theme", "import"]).is_err());
+        assert_eq!(
+            parse(&["theme", "list"]).unwrap().command,
+            CliCommand::Theme(ThemeCommand::List)
+        );
+    }
+
+    #[test]
+    fn replay_conflicts_with_capture_and_tty() {
+        assert!(parse(&["--replay-raw", "a.txt", "--capture-raw", "b.txt"]).is_err());
+        assert!(parse(&["--replay-raw", "a.txt", "--tty", "/dev/ttyACM0"]).is_err());
+
+        let args = parse(&["--replay-raw", "a.txt", "-c", "my.toml"]).unwrap();
+        assert_eq!(args.replay_raw, Some("a.txt".into()));
+        assert_eq!(args.config_path, Some("my.toml".into()));
+    }
+
+    #[test]
+    fn tty_is_not_looked_up_while_parsing() {
+        let args = parse(&["--tty", "/dev/does-not-exist"]).unwrap();
+        assert_eq!(args.tty, Some("/dev/does-not-exist".into()));
+        assert_eq!(options(&["--tty", "/dev/does-not-exist"]).custom_tty, None);
+    }
+
+    #[test]
+    fn imported_themes_keep_other_settings() {
+        let dir = std::env::temp_dir().join(format!("b0xx_viewer_import_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("b0xx_viewer_config.toml");
+        std::fs::write(
+            &path,
+            "version = 2\nscale = 2.0\ndisplay_labels = false\n[serial]\nbaud_rate = 9600\n[button_active_colors]\na = \"#123456\"\n",
+        )
+        .unwrap();
+
+        let monochrome: ViewerOptions = toml::Value::Table(bundled_theme("monochrome").unwrap())
+            .try_into()
+            .unwrap();
+        let theme = ViewerTheme::from(&monochrome);
+        import_theme(&path, &theme.to_code()).unwrap();
+
+        let imported: ViewerOptions =
+            toml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(ViewerTheme::from(&imported), theme);
+        assert_eq!(imported.scale, 2.);
+        assert_eq!(imported.serial.baud_rate, 9600);
+
+        // Invalid codes leave the file alone
+        let before = std::fs::read_to_string(&path).unwrap();
+        assert!(import_theme(&path, "b0xx-theme1:nope").is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), before);
+
+        // A missing file is created with the current version
+        let new_path = dir.join("new").join("b0xx_viewer_config.toml");
+        import_theme(&new_path, &theme.to_code()).unwrap();
+        let created: toml::value::Table =
+            toml::from_str(&std::fs::read_to_string(&new_path).unwrap()).unwrap();
+        assert_eq!(created["version"].as_integer(), Some(CONFIG_VERSION as i64));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// The only test changing the environment, other tests must not depend on these variables
+    #[cfg(all(unix, not(target_os = "macos")))]
+    #[test]
+    fn config_search_order() {
+        let root = std::env::temp_dir().join(format!("b0xx_viewer_search_{}", std::process::id()));
+        let file = |dir: &str| {
+            root.join(dir)
+                .join("b0xx_viewer")
+                .join("b0xx_viewer_config.toml")
+        };
+        std::env::set_var("XDG_CONFIG_HOME", root.join("home"));
+        std::env::set_var(
+            "XDG_CONFIG_DIRS",
+            format!(
+                "{}:relative:{}",
+                root.join("etc").display(),
+                root.join("opt").display()
+            ),
+        );
+        std::env::remove_var(CONFIG_PATH_ENV);
+
+        let exe_path = ViewerOptions::exe_config_path().unwrap();
+        assert_eq!(
+            ViewerOptions::search_paths(),
+            [file("home"), file("etc"), file("opt"), exe_path]
+        );
+
+        // The first existing file wins
+        assert_eq!(parse(&[]).unwrap().find_config_path(), None);
+        std::fs::create_dir_all(file("opt").parent().unwrap()).unwrap();
+        std::fs::write(file("opt"), "").unwrap();
+        assert_eq!(parse(&[]).unwrap().find_config_path(), Some(file("opt")));
+
+        // --init_config writes to the user config dir, which is searched first
+        assert_eq!(init_config(), 0);
+        assert_eq!(parse(&[]).unwrap().find_config_path(), Some(file("home")));
+        std::fs::write(file("home"), "# mine\n").unwrap();
+        assert_eq!(init_config(), 1);
+        assert_eq!(std::fs::read_to_string(file("home")).unwrap(), "# mine\n");
+
+        // Explicit paths don't have to exist, -c comes before the environment
+        std::env::set_var(CONFIG_PATH_ENV, root.join("env.toml"));
+        assert_eq!(
+            parse(&[]).unwrap().find_config_path(),
+            Some(root.join("env.toml"))
+        );
+        assert_eq!(
+            parse(&["-c", "cli.toml"]).unwrap().find_config_path(),
+            Some("cli.toml".into())
+        );
+        std::env::set_var(CONFIG_PATH_ENV, "");
+        assert_eq!(parse(&[]).unwrap().find_config_path(), Some(file("home")));
+
+        std::env::remove_var(CONFIG_PATH_ENV);
+        std::env::remove_var("XDG_CONFIG_DIRS");
+        std::env::remove_var("XDG_CONFIG_HOME");
+        std::fs::remove_dir_all(&root).unwrap();
+    }
 }
@@ -0,0 +1,250 @@
+use super::{ViewerColor, ViewerOptions, BUTTON_NAMES};
+
+/// WCAG 2.1 minimum contrast for user interface components (success criterion 1.4.11)
+pub const MIN_STATE_CONTRAST: f32 = 3.0;
+
+/// Color vision the themes are checked against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorVision {
+    Typical,
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl ColorVision {
+    pub const ALL: [ColorVision; 4] = [
+        ColorVision::Typical,
+        ColorVision::Protanopia,
+        ColorVision::Deuteranopia,
+        ColorVision::Tritanopia,
+    ];
+
+    /// Machado, Oliveira & Fernandes (2009) simulation matrices at full severity, in linear RGB
+    fn matrix(self) -> Option<[[f32; 3]; 3]> {
+        match self {
+            ColorVision::Typical => None,
+            ColorVision::Protanopia => Some([
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ]),
+            ColorVision::Deuteranopia => Some([
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ]),
+            ColorVision::Tritanopia => Some([
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ]),
+        }
+    }
+}
+
+impl std::fmt::Display for ColorVision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColorVision::Typical => write!(f, "typical color vision"),
+            ColorVision::Protanopia => write!(f, "protanopia"),
+            ColorVision::Deuteranopia => write!(f, "deuteranopia"),
+            ColorVision::Tritanopia => write!(f, "tritanopia"),
+        }
+    }
+}
+
+fn to_linear(channel: u8) -> f32 {
+    let c = channel as f32 / 255.;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn from_linear(channel: f32) -> u8 {
+    let c = channel.clamp(0., 1.);
+    let c = if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1. / 2.4) - 0.055
+    };
+    (c * 255.).round() as u8
+}
+
+impl ViewerColor {
+    fn opaque(r: u8, g: u8, b: u8) -> Self {
+        Self(rgb::RGBA8::new(r, g, b, 255))
+    }
+
+    /// The opaque color seen when drawing this color over `background`.
+    /// The background itself is considered opaque
+    pub fn over(self, background: ViewerColor) -> Self {
+        let alpha = self.a as f32 / 255.;
+        let blend = |fg: u8, bg: u8| (fg as f32 * alpha + bg as f32 * (1. - alpha)).round() as u8;
+        Self::opaque(
+            blend(self.r, background.r),
+            blend(self.g, background.g),
+            blend(self.b, background.b),
+        )
+    }
+
+    /// WCAG relative luminance, from 0 (black) to 1 (white)
+    pub fn relative_luminance(self) -> f32 {
+        0.2126 * to_linear(self.r) + 0.7152 * to_linear(self.g) + 0.0722 * to_linear(self.b)
+    }
+
+    /// WCAG contrast ratio, from 1:1 for identical luminances to 21:1 for black on white
+    pub fn contrast_ratio(self, other: ViewerColor) -> f32 {
+        let (a, b) = (self.relative_luminance(), other.relative_luminance());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    /// How this color appears with the given color vision
+    pub fn simulate(self, vision: ColorVision) -> Self {
+        let matrix = match vision.matrix() {
+            Some(matrix) => matrix,
+            None => return self,
+        };
+
+        let rgb = [to_linear(self.r), to_linear(self.g), to_linear(self.b)];
+        let [r, g, b] =
+            matrix.map(|row| from_linear(row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2]));
+        Self(rgb::RGBA8::new(r, g, b, self.a))
+    }
+
+    /// Black or white, whichever is the most readable on top of this color.
+    /// On an opaque color, the contrast is always above the 4.5:1 WCAG asks of text
+    pub fn readable_text_color(self) -> Self {
+        let (black, white) = (Self::opaque(0, 0, 0), Self::opaque(255, 255, 255));
+        if self.contrast_ratio(black) >= self.contrast_ratio(white) {
+            black
+        } else {
+            white
+        }
+    }
+}
+
+/// Checks that pressed and released buttons can be told apart, with typical color vision as well
+/// as simulated protanopia, deuteranopia and tritanopia. Returns one warning per problem found
+pub fn theme_warnings(options: &ViewerOptions) -> Vec<String> {
+    let background = options.background_color;
+    let background = ViewerColor::opaque(background.r, background.g, background.b);
+    let inactive = options.button_inactive_colors.to_array();
+    let active = options.button_active_colors.to_array();
+
+    let mut warnings = vec![];
+    let mut reported: Vec<&str> = vec![];
+    for vision in ColorVision::ALL {
+        let hard_to_tell: Vec<&str> = BUTTON_NAMES
+            .iter()
+            .zip(inactive.iter().zip(active.iter()))
+            .filter(|(name, _)| !reported.contains(name))
+            .filter(|(_, (inactive, active))| {
+                let released = if options.colored_rims {
+                    background
+                } else {
+                    inactive.over(background)
+                };
+                let pressed = active.over(background);
+                released
+                    .simulate(vision)
+                    .contrast_ratio(pressed.simulate(vision))
+                    < MIN_STATE_CONTRAST
+            })
+            .map(|(name, _)| *name)
+            .collect();
+
+        if !hard_to_tell.is_empty() {
+            warnings.push(format!(
+                "pressed and released buttons are hard to tell apart with {} (contrast below {}:1): {}",
+                vision,
+                MIN_STATE_CONTRAST,
+                hard_to_tell.join(", ")
+            ));
+            reported.extend(hard_to_tell);
+        }
+    }
+
+    if options.colored_rims {
+        let faint: Vec<&str> = BUTTON_NAMES
+            .iter()
+            .zip(inactive.iter())
+            .filter(|(_, rim)| rim.over(background).contrast_ratio(background) < MIN_STATE_CONTRAST)
+            .map(|(name, _)| *name)
+            .collect();
+
+        if !faint.is_empty() {
+            warnings.push(format!(
+                "button rims are hard to see on the background (contrast below {}:1): {}",
+                MIN_STATE_CONTRAST,
+                faint.join(", ")
+            ));
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color(hex: &str) -> ViewerColor {
+        hex.parse().unwrap()
+    }
+
+    fn themed(name: &str) -> ViewerOptions {
+        toml::Value::Table(super::super::bundled_theme(name).unwrap())
+            .try_into()
+            .unwrap()
+    }
+
+    #[test]
+    fn contrast_ratios() {
+        let (black, white) = (color("black"), color("white"));
+        assert!((black.contrast_ratio(white) - 21.).abs() < 0.01);
+        assert_eq!(white.contrast_ratio(black), black.contrast_ratio(white));
+        assert_eq!(black.contrast_ratio(black), 1.);
+
+        assert_eq!(color("#FFFFFF00").over(black), black);
+        assert_eq!(color("#FFFFFF80").over(black), color("#808080"));
+
+        assert_eq!(color("#FFFF00").readable_text_color(), black);
+        assert_eq!(color("#000080").readable_text_color(), white);
+    }
+
+    #[test]
+    fn color_vision_simulation() {
+        let red = color("red");
+        assert_eq!(red.simulate(ColorVision::Typical), red);
+        // Without red cones, red is seen as a dark olive. Grays are left alone
+        let protanopic_red = red.simulate(ColorVision::Protanopia);
+        assert!(protanopic_red.r.abs_diff(protanopic_red.g) < 20, "{:?}", protanopic_red);
+        assert!(protanopic_red.relative_luminance() < red.relative_luminance());
+        for vision in ColorVision::ALL {
+            let white = color("white");
+            assert!(white.simulate(vision).contrast_ratio(white) < 1.05, "{}", vision);
+        }
+    }
+
+    #[test]
+    fn accessible_themes_pass_contrast_checks() {
+        for name in ["b0xx", "colorblind", "gcc-accessible", "high-contrast", "monochrome"] {
+            assert_eq!(theme_warnings(&themed(name)), Vec::<String>::new(), "{}", name);
+        }
+        assert!(!theme_warnings(&themed("gcc")).is_empty());
+    }
+
+    #[test]
+    fn faint_rims_are_reported() {
+        let mut options = ViewerOptions {
+            colored_rims: true,
+            ..Default::default()
+        };
+        options.button_inactive_colors.a = options.background_color;
+        let warnings = theme_warnings(&options);
+        assert!(warnings.iter().any(|warning| warning.starts_with("button rims")), "{:?}", warnings);
+    }
+}
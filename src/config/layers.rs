@@ -0,0 +1,879 @@
+use super::{ConfigError, ViewerOptions};
+use crate::error::ViewerError;
+use std::collections::BTreeMap;
+
+/// Prefix of the environment variables overriding configuration values.
+/// Nested keys are separated with a double underscore, eg. `B0XX_VIEWER_BUTTON_ACTIVE_COLORS__A`
+pub const ENV_PREFIX: &str = "B0XX_VIEWER_";
+
+/// Where an effective configuration value comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSource {
+    Default,
+    Theme(String),
+    File(std::path::PathBuf),
+    Profile {
+        name: String,
+        path: std::path::PathBuf,
+    },
+    Env(String),
+    Cli(String),
+    Runtime(String),
+}
+
+impl std::fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigSource::Default => write!(f, "built-in default"),
+            ConfigSource::Theme(name) => write!(f, "bundled theme `{}`", name),
+            ConfigSource::File(path) => write!(f, "config file {}", path.display()),
+            ConfigSource::Profile { name, path } => {
+                write!(f, "profile `{}` in config file {}", name, path.display())
+            }
+            ConfigSource::Env(var) => write!(f, "environment variable {}", var),
+            ConfigSource::Cli(arg) => write!(f, "command line {}", arg),
+            ConfigSource::Runtime(what) => write!(f, "{}", what),
+        }
+    }
+}
+
+/// A partial configuration: only the keys it sets take part in the merge
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigLayer {
+    values: toml::value::Table,
+    sources: BTreeMap<String, ConfigSource>,
+    /// Named partial configurations found in a config file's `[profiles.<name>]` tables
+    profiles: BTreeMap<String, ConfigLayer>,
+}
+
+fn join_key(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+/// Colors written as `{ r, g, b, a }` tables are single values, they are never merged channel by channel.
+/// Button color tables have `r`, `b` and `a` keys too, but they hold colors rather than numbers
+fn is_color_table(table: &toml::value::Table) -> bool {
+    !table.is_empty()
+        && table
+            .iter()
+            .all(|(key, value)| matches!(key.as_str(), "r" | "g" | "b" | "a") && value.is_integer())
+}
+
+/// Deep-merges `other` into `base`, tables are merged key by key while any other value, colors
+/// included, replaces what was there. Provenance is recorded for every leaf that gets written
+fn merge_table(
+    base: &mut toml::value::Table,
+    other: &toml::value::Table,
+    prefix: &str,
+    source_of: &dyn Fn(&str) -> ConfigSource,
+    provenance: &mut BTreeMap<String, ConfigSource>,
+) {
+    for (key, value) in other {
+        let path = join_key(prefix, key);
+        match (base.get_mut(key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(other_table))
+                if !is_color_table(other_table) =>
+            {
+                merge_table(base_table, other_table, &path, source_of, provenance);
+            }
+            (_, toml::Value::Table(other_table)) if !is_color_table(other_table) => {
+                let mut table = toml::value::Table::new();
+                clear_provenance(provenance, &path);
+                merge_table(&mut table, other_table, &path, source_of, provenance);
+                base.insert(key.clone(), toml::Value::Table(table));
+            }
+            (_, value) => {
+                clear_provenance(provenance, &path);
+                provenance.insert(path.clone(), source_of(&path));
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+fn clear_provenance(provenance: &mut BTreeMap<String, ConfigSource>, path: &str) {
+    let nested = format!("{}.", path);
+    provenance.retain(|key, _| key != path && !key.starts_with(&nested));
+}
+
+impl ConfigLayer {
+    pub fn empty() -> Self {
+        Self {
+            values: toml::value::Table::new(),
+            sources: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+        }
+    }
+
+    pub fn from_table(values: toml::value::Table, source: ConfigSource) -> Self {
+        let mut layer = Self::empty();
+        merge_table(
+            &mut layer.values,
+            &values,
+            "",
+            &|_| source.clone(),
+            &mut layer.sources,
+        );
+        layer
+    }
+
+    pub fn defaults() -> Self {
+        match toml::Value::try_from(ViewerOptions::default()) {
+            Ok(toml::Value::Table(values)) => Self::from_table(values, ConfigSource::Default),
+            _ => unreachable!("default options always serialize to a table"),
+        }
+    }
+
+    /// Loads a configuration file, migrating it to the current version first
+    pub fn from_file(
+        path: &std::path::Path,
+        migration: super::FileMigration,
+    ) -> Result<Self, ViewerError> {
+        if !path.exists() {
+            return Err(ConfigError::NotFound.into());
+        }
+
+        let buf = std::fs::read(path)?;
+        let mut values: toml::value::Table =
+            toml::de::from_slice(&buf).map_err(|e| ViewerError::from(ConfigError::from(e)))?;
+
+        super::migrate_file(path, &mut values, migration)?;
+        for key in super::unknown_keys(&values) {
+            warn!("{}: unknown configuration key `{}`, ignoring it", path.display(), key);
+        }
+
+        Ok(Self::from_file_table(path, values))
+    }
+
+    /// Builds the layer of a config file, setting its profiles aside
+    pub fn from_file_table(path: &std::path::Path, mut values: toml::value::Table) -> Self {
+        let profiles = match values.remove("profiles") {
+            Some(toml::Value::Table(profiles)) => profiles,
+            Some(_) => {
+                warn!("{}: `profiles` must be a table, ignoring it", path.display());
+                toml::value::Table::new()
+            }
+            None => toml::value::Table::new(),
+        };
+
+        let mut layer = Self::from_table(values, ConfigSource::File(path.into()));
+        for (name, profile) in profiles {
+            let profile = match profile {
+                toml::Value::Table(profile) => profile,
+                _ => {
+                    warn!("{}: profile `{}` must be a table, ignoring it", path.display(), name);
+                    continue;
+                }
+            };
+
+            let source = ConfigSource::Profile {
+                name: name.clone(),
+                path: path.into(),
+            };
+            layer.profiles.insert(name, Self::from_table(profile, source));
+        }
+
+        layer
+    }
+
+    /// Loads a bundled theme, `None` if there is no theme with this name
+    pub fn bundled_theme(name: &str) -> Option<Self> {
+        let values = super::bundled_theme(name)?;
+        Some(Self::from_table(values, ConfigSource::Theme(name.into())))
+    }
+
+    /// Reads the `profile` key of this layer. An empty name selects no profile
+    fn profile(&self) -> Option<&str> {
+        self.values.get("profile").and_then(toml::Value::as_str)
+    }
+
+    /// Reads the `theme` key of this layer. An empty name selects no theme
+    fn theme(&self) -> Option<&str> {
+        self.values.get("theme").and_then(toml::Value::as_str)
+    }
+
+    /// Collects `B0XX_VIEWER_*` variables. Values are parsed as TOML when possible, as plain strings otherwise
+    pub fn from_env() -> Self {
+        let layer = Self::from_vars(std::env::vars());
+        for (var, key) in layer.unknown_keys() {
+            warn!("{}: unknown configuration key `{}`, ignoring it", var, key);
+        }
+
+        layer
+    }
+
+    fn from_vars(vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        let mut layer = Self::empty();
+        for (var, raw) in vars {
+            if var == super::CONFIG_PATH_ENV {
+                continue;
+            }
+
+            let key = match var.strip_prefix(ENV_PREFIX) {
+                Some(key) if !key.is_empty() => key,
+                _ => continue,
+            };
+
+            let path = key.to_lowercase().replace("__", ".");
+            layer.set(&path, parse_env_value(&raw), ConfigSource::Env(var.clone()));
+        }
+
+        layer
+    }
+
+    /// Keys of this layer that aren't configuration settings, along with where they come from
+    fn unknown_keys(&self) -> Vec<(String, String)> {
+        super::unknown_keys(&self.values)
+            .into_iter()
+            .map(|key| {
+                let nested = format!("{}.", key);
+                let source = self
+                    .sources
+                    .iter()
+                    .find(|(path, _)| **path == key || path.starts_with(&nested))
+                    .map_or_else(
+                        || key.clone(),
+                        |(_, source)| match source {
+                            ConfigSource::Env(var) => var.clone(),
+                            source => source.to_string(),
+                        },
+                    );
+                (source, key)
+            })
+            .collect()
+    }
+
+    /// Sets a single value at a dotted key path, eg. `detection.relax_arduino`
+    pub fn set(&mut self, path: &str, value: toml::Value, source: ConfigSource) {
+        let mut nested = value;
+        for key in path.rsplit('.') {
+            let mut table = toml::value::Table::new();
+            table.insert(key.to_string(), nested);
+            nested = toml::Value::Table(table);
+        }
+
+        if let toml::Value::Table(table) = nested {
+            merge_table(
+                &mut self.values,
+                &table,
+                "",
+                &|_| source.clone(),
+                &mut self.sources,
+            );
+        }
+    }
+}
+
+fn parse_env_value(raw: &str) -> toml::Value {
+    toml::from_str::<toml::value::Table>(&format!("value = {}", raw))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+/// Configuration layers in increasing order of priority:
+/// built-in defaults < bundled theme < config file < active profile < environment < command line < runtime changes.
+/// A theme selected from the environment, the command line or at runtime goes right above the active profile instead
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayeredConfig {
+    pub defaults: ConfigLayer,
+    pub file: Option<(std::path::PathBuf, ConfigLayer)>,
+    pub env: ConfigLayer,
+    pub cli: ConfigLayer,
+    /// Changes made while the viewer is running, eg. switching profiles
+    pub runtime: ConfigLayer,
+}
+
+impl Default for LayeredConfig {
+    fn default() -> Self {
+        Self {
+            defaults: ConfigLayer::defaults(),
+            file: None,
+            env: ConfigLayer::empty(),
+            cli: ConfigLayer::empty(),
+            runtime: ConfigLayer::empty(),
+        }
+    }
+}
+
+/// The effective configuration along with where each of its values comes from
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedConfig {
+    pub options: ViewerOptions,
+    pub values: toml::value::Table,
+    pub provenance: BTreeMap<String, ConfigSource>,
+}
+
+impl LayeredConfig {
+    fn file_layer(&self) -> Option<&ConfigLayer> {
+        self.file.as_ref().map(|(_, layer)| layer)
+    }
+
+    /// Names of the profiles defined in the config file, in alphabetical order
+    pub fn profile_names(&self) -> Vec<String> {
+        self.file_layer()
+            .map(|layer| layer.profiles.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// The profile name set by the highest priority layer, an empty name selects no profile
+    fn selected_profile(&self) -> Option<&str> {
+        [
+            Some(&self.runtime),
+            Some(&self.cli),
+            Some(&self.env),
+            self.file_layer(),
+        ]
+        .into_iter()
+        .flatten()
+        .find_map(ConfigLayer::profile)
+        .filter(|name| !name.is_empty())
+    }
+
+    /// The profile selected by the highest priority layer, if it exists
+    pub fn active_profile(&self) -> Option<String> {
+        let name = self.selected_profile()?;
+        self.profile_names()
+            .into_iter()
+            .find(|profile| profile == name)
+    }
+
+    /// The selected profile when there is no profile by that name, eg. after a typo.
+    /// The base configuration is used instead
+    pub fn unknown_profile(&self) -> Option<&str> {
+        self.selected_profile()
+            .filter(|_| self.active_profile().is_none())
+    }
+
+    /// Switches to another profile while running, `None` goes back to the base configuration
+    pub fn select_profile(&mut self, name: Option<&str>) {
+        self.runtime.set(
+            "profile",
+            toml::Value::String(name.unwrap_or_default().into()),
+            ConfigSource::Runtime("profile hotkey".into()),
+        );
+    }
+
+    fn profile_layer(&self, profile: Option<&str>) -> Option<&ConfigLayer> {
+        profile.and_then(|name| {
+            self.file_layer()
+                .and_then(|layer| layer.profiles.get(name))
+        })
+    }
+
+    /// The bundled theme selected by the highest priority layer, profiles included, and whether it
+    /// was picked above the config file, eg. with `--theme`. Such a theme overrides the file
+    /// instead of serving as its base
+    fn selected_theme(&self, profile: Option<&str>) -> Option<(String, bool)> {
+        let (position, name) = [
+            Some(&self.runtime),
+            Some(&self.cli),
+            Some(&self.env),
+            self.profile_layer(profile),
+            self.file_layer(),
+        ]
+        .into_iter()
+        .enumerate()
+        .find_map(|(position, layer)| Some((position, layer?.theme()?)))?;
+
+        if name.is_empty() {
+            return None;
+        }
+
+        if !super::bundled_theme_names().any(|theme| theme == name) {
+            warn!(
+                "Unknown theme `{}`, ignoring it. Available themes: {}",
+                name,
+                super::bundled_theme_names().collect::<Vec<_>>().join(", ")
+            );
+            return None;
+        }
+
+        Some((name.to_string(), position < 3))
+    }
+
+    fn layers<'a>(
+        &'a self,
+        theme: Option<(&'a ConfigLayer, bool)>,
+        profile: Option<&'a str>,
+    ) -> impl Iterator<Item = &'a ConfigLayer> {
+        let (base_theme, explicit_theme) = match theme {
+            Some((layer, true)) => (None, Some(layer)),
+            Some((layer, false)) => (Some(layer), None),
+            None => (None, None),
+        };
+
+        std::iter::once(&self.defaults)
+            .chain(base_theme)
+            .chain(self.file_layer())
+            .chain(self.profile_layer(profile))
+            .chain(explicit_theme)
+            .chain(std::iter::once(&self.env))
+            .chain(std::iter::once(&self.cli))
+            .chain(std::iter::once(&self.runtime))
+    }
+
+    pub fn resolve(&self) -> Result<ResolvedConfig, ViewerError> {
+        let mut values = toml::value::Table::new();
+        let mut provenance = BTreeMap::new();
+        let profile = self.active_profile();
+        let theme = self.selected_theme(profile.as_deref());
+        let theme_layer = theme
+            .as_ref()
+            .and_then(|(name, explicit)| Some((ConfigLayer::bundled_theme(name)?, *explicit)));
+
+        let layers = self.layers(
+            theme_layer.as_ref().map(|(layer, explicit)| (layer, *explicit)),
+            profile.as_deref(),
+        );
+        for layer in layers {
+            merge_table(
+                &mut values,
+                &layer.values,
+                "",
+                &|path| {
+                    layer
+                        .sources
+                        .get(path)
+                        .cloned()
+                        .unwrap_or(ConfigSource::Default)
+                },
+                &mut provenance,
+            );
+        }
+
+        let theme = theme.map(|(name, _)| name);
+        if let Some(name) = &theme {
+            let source = ConfigSource::Theme(name.clone());
+            if !provenance.values().any(|value| value == &source) {
+                warn!(
+                    "Theme `{}` has no effect, every setting it changes is overridden by the configuration",
+                    name
+                );
+            }
+        }
+
+        let mut options: ViewerOptions = toml::Value::Table(values.clone())
+            .try_into()
+            .map_err(|e| ViewerError::from(ConfigError::from(e)))?;
+
+        if let Some((path, _)) = &self.file {
+            options.path = path.clone();
+        }
+        options.profile = profile;
+        options.theme = theme;
+
+        Ok(ResolvedConfig {
+            options,
+            values,
+            provenance,
+        })
+    }
+}
+
+impl ResolvedConfig {
+    /// One line per effective value: `key = value (source)`
+    pub fn explain(&self) -> Vec<String> {
+        let mut values = BTreeMap::new();
+        flatten_table(&self.values, "", &mut values);
+
+        values
+            .into_iter()
+            .map(|(path, value)| format!("{} = {} ({})", path, value, self.source_of(&path)))
+            .collect()
+    }
+
+    /// Where the value at `path` comes from. Fields of a color, eg. `background_color.r`, come from
+    /// the source of the whole color, while tables are attributed to the source of their fields
+    pub fn source_of(&self, path: &str) -> &ConfigSource {
+        let nested = format!("{}.", path);
+        self.provenance
+            .get(path)
+            .or_else(|| {
+                let (parent, _) = path.rsplit_once('.')?;
+                self.provenance.get(parent)
+            })
+            .or_else(|| {
+                self.provenance
+                    .iter()
+                    .find(|(key, _)| key.starts_with(&nested))
+                    .map(|(_, source)| source)
+            })
+            .unwrap_or(&ConfigSource::Default)
+    }
+
+    /// The effective configuration, in the same format as configuration files
+    pub fn to_toml(&self) -> Result<String, ViewerError> {
+        toml::Value::try_from(&self.options)
+            .and_then(|value| toml::to_string(&value))
+            .map_err(|e| ViewerError::from(ConfigError::SerializationError(e)))
+    }
+
+    /// One line per effective value that differs from the built-in defaults:
+    /// `key = value (default: value, source)`
+    pub fn diff_from_defaults(&self) -> Result<Vec<String>, ViewerError> {
+        let flatten = |options: &ViewerOptions| -> Result<_, ViewerError> {
+            let mut values = BTreeMap::new();
+            match toml::Value::try_from(options) {
+                Ok(toml::Value::Table(table)) => flatten_table(&table, "", &mut values),
+                Ok(_) => unreachable!("options always serialize to a table"),
+                Err(e) => return Err(ConfigError::SerializationError(e).into()),
+            }
+            Ok(values)
+        };
+
+        let defaults = flatten(&ViewerOptions::default())?;
+        let effective = flatten(&self.options)?;
+
+        let mut keys: Vec<&String> = defaults.keys().chain(effective.keys()).collect();
+        keys.sort();
+        keys.dedup();
+
+        Ok(keys
+            .into_iter()
+            .filter(|key| defaults.get(*key) != effective.get(*key))
+            .map(|key| {
+                let show = |value: Option<&toml::Value>| {
+                    value.map_or_else(|| "unset".to_string(), ToString::to_string)
+                };
+                format!(
+                    "{} = {} (default: {}, {})",
+                    key,
+                    show(effective.get(key)),
+                    show(defaults.get(key)),
+                    self.source_of(key)
+                )
+            })
+            .collect())
+    }
+}
+
+/// Collects the leaves of `table` by dotted key path. Arrays are kept whole
+fn flatten_table(
+    table: &toml::value::Table,
+    prefix: &str,
+    values: &mut BTreeMap<String, toml::Value>,
+) {
+    for (key, value) in table {
+        let path = join_key(prefix, key);
+        match value {
+            toml::Value::Table(nested) => flatten_table(nested, &path, values),
+            value => {
+                values.insert(path, value.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ViewerColor;
+
+    fn file_layer(text: &str) -> Option<(std::path::PathBuf, ConfigLayer)> {
+        let path = std::path::PathBuf::from("b0xx_viewer_config.toml");
+        let values = toml::from_str(text).unwrap();
+        Some((path.clone(), ConfigLayer::from_file_table(&path, values)))
+    }
+
+    #[test]
+    fn explicit_themes_override_the_file() {
+        let monochrome_a = "#F0F0F0".parse().unwrap();
+        let mut config = LayeredConfig {
+            file: file_layer("[button_active_colors]\na = \"#00EBFF\""),
+            ..Default::default()
+        };
+        config.cli.set(
+            "theme",
+            toml::Value::String("monochrome".into()),
+            ConfigSource::Cli("--theme".into()),
+        );
+        let resolved = config.resolve().unwrap();
+        assert_eq!(resolved.options.button_active_colors.a, monochrome_a);
+        assert_eq!(
+            resolved.source_of("button_active_colors.a"),
+            &ConfigSource::Theme("monochrome".into())
+        );
+
+        // Picked by the file itself, the theme is only a base
+        let config = LayeredConfig {
+            file: file_layer("theme = \"monochrome\"\n[button_active_colors]\na = \"#00EBFF\""),
+            ..Default::default()
+        };
+        let options = config.resolve().unwrap().options;
+        assert_eq!(options.button_active_colors.a, "#00EBFF".parse().unwrap());
+        assert_eq!(options.button_active_colors.b, monochrome_a);
+    }
+
+    #[test]
+    fn default_colors_from_the_file_are_kept() {
+        let default_color = toml::Value::try_from(ViewerColor::default()).unwrap();
+        let config = LayeredConfig {
+            file: file_layer(&format!(
+                "theme = \"monochrome\"\n[button_active_colors]\na = {}",
+                default_color
+            )),
+            ..Default::default()
+        };
+        let resolved = config.resolve().unwrap();
+        assert_eq!(
+            resolved.options.button_active_colors.a,
+            ViewerColor::default()
+        );
+        assert_eq!(
+            resolved.source_of("button_active_colors.a"),
+            &ConfigSource::File("b0xx_viewer_config.toml".into())
+        );
+        assert_eq!(
+            resolved.source_of("button_active_colors.b"),
+            &ConfigSource::Theme("monochrome".into())
+        );
+    }
+
+    #[test]
+    fn provenance_follows_priority() {
+        let mut config = LayeredConfig {
+            file: file_layer(
+                "[button_active_colors]\na = \"#FF0000\"\nb = \"#FF0000\"\nx = \"#FF0000\"",
+            ),
+            ..Default::default()
+        };
+        for key in ["b", "x"] {
+            let var = format!("B0XX_VIEWER_BUTTON_ACTIVE_COLORS__{}", key.to_uppercase());
+            config.env.set(
+                &format!("button_active_colors.{}", key),
+                toml::Value::String("#00FF00".into()),
+                ConfigSource::Env(var),
+            );
+        }
+        config.cli.set(
+            "button_active_colors.x",
+            toml::Value::String("#0000FF".into()),
+            ConfigSource::Cli("--button-active-colors".into()),
+        );
+
+        let resolved = config.resolve().unwrap();
+        let colors = &resolved.options.button_active_colors;
+        assert_eq!(colors.a, "#FF0000".parse().unwrap());
+        assert_eq!(colors.b, "#00FF00".parse().unwrap());
+        assert_eq!(colors.x, "#0000FF".parse().unwrap());
+        assert_eq!(
+            resolved.source_of("button_active_colors.a"),
+            &ConfigSource::File("b0xx_viewer_config.toml".into())
+        );
+        assert_eq!(
+            resolved.source_of("button_active_colors.b"),
+            &ConfigSource::Env("B0XX_VIEWER_BUTTON_ACTIVE_COLORS__B".into())
+        );
+        assert_eq!(
+            resolved.source_of("button_active_colors.x"),
+            &ConfigSource::Cli("--button-active-colors".into())
+        );
+        assert_eq!(
+            resolved.source_of("button_active_colors.y"),
+            &ConfigSource::Default
+        );
+    }
+
+    #[test]
+    fn replaced_tables_drop_their_provenance() {
+        let mut values = toml::value::Table::new();
+        let mut provenance = BTreeMap::new();
+        let file: toml::value::Table =
+            toml::from_str("[serial]\nbaud_rate = 1\ntimeout_ms = 2").unwrap();
+        merge_table(
+            &mut values,
+            &file,
+            "",
+            &|_| ConfigSource::Default,
+            &mut provenance,
+        );
+        assert_eq!(
+            provenance.keys().collect::<Vec<_>>(),
+            ["serial.baud_rate", "serial.timeout_ms"]
+        );
+
+        let env: toml::value::Table = toml::from_str("serial = \"fast\"").unwrap();
+        let source = ConfigSource::Env("B0XX_VIEWER_SERIAL".into());
+        merge_table(&mut values, &env, "", &|_| source.clone(), &mut provenance);
+        assert_eq!(values["serial"].as_str(), Some("fast"));
+        assert_eq!(
+            provenance.into_iter().collect::<Vec<_>>(),
+            [("serial".into(), source)]
+        );
+    }
+
+    #[test]
+    fn colors_are_replaced_whole() {
+        let mut config = LayeredConfig {
+            file: file_layer("background_color = { r = 10, g = 20, b = 30, a = 128 }"),
+            ..Default::default()
+        };
+        let source = ConfigSource::Env("B0XX_VIEWER_BACKGROUND_COLOR".into());
+        config.env.set(
+            "background_color",
+            toml::from_str("r = 1\ng = 2\nb = 3").unwrap(),
+            source.clone(),
+        );
+
+        let resolved = config.resolve().unwrap();
+        assert_eq!(
+            resolved.options.background_color,
+            ViewerColor(rgb::RGBA8::new(1, 2, 3, 255))
+        );
+        assert_eq!(resolved.source_of("background_color"), &source);
+        assert_eq!(resolved.source_of("background_color.a"), &source);
+        assert!(resolved
+            .explain()
+            .iter()
+            .filter(|line| line.starts_with("background_color."))
+            .all(|line| line.ends_with("(environment variable B0XX_VIEWER_BACKGROUND_COLOR)")));
+    }
+
+    #[test]
+    fn unknown_env_vars_are_reported() {
+        let layer = ConfigLayer::from_vars(
+            [
+                ("B0XX_VIEWER_DISPLAY_LABELS", "true"),
+                ("B0XX_VIEWER_DISPLAY_LABLES", "true"),
+                ("B0XX_VIEWER_DETECTION__RELAX_ARDUINOS", "true"),
+                ("B0XX_VIEWER_CONFIG_PATH", "/tmp/b0xx_viewer_config.toml"),
+                ("HOME", "/root"),
+            ]
+            .map(|(var, value)| (var.to_string(), value.to_string())),
+        );
+        assert_eq!(
+            layer.unknown_keys(),
+            [
+                (
+                    "B0XX_VIEWER_DETECTION__RELAX_ARDUINOS".to_string(),
+                    "detection.relax_arduinos".to_string()
+                ),
+                (
+                    "B0XX_VIEWER_DISPLAY_LABLES".to_string(),
+                    "display_lables".to_string()
+                ),
+            ]
+        );
+        assert_eq!(layer.values["display_labels"].as_bool(), Some(true));
+    }
+
+    #[test]
+    fn explained_and_diffed_outputs() {
+        let mut config = LayeredConfig {
+            file: file_layer("[button_active_colors]\na = \"#FF0000\""),
+            ..Default::default()
+        };
+        config.cli.set(
+            "display_labels",
+            toml::Value::Boolean(true),
+            ConfigSource::Cli("--labels".into()),
+        );
+        let resolved = config.resolve().unwrap();
+
+        let explained = resolved.explain();
+        for line in [
+            "button_active_colors.a = \"#FF0000\" (config file b0xx_viewer_config.toml)",
+            "display_labels = true (command line --labels)",
+            "colored_rims = false (built-in default)",
+        ] {
+            assert!(
+                explained.iter().any(|explained| explained == line),
+                "{}",
+                line
+            );
+        }
+
+        let default_a = ViewerOptions::default().button_active_colors.a.to_hex();
+        assert_eq!(
+            resolved.diff_from_defaults().unwrap(),
+            [
+                format!(
+                    "button_active_colors.a = \"#FF0000\" (default: \"{}\", config file b0xx_viewer_config.toml)",
+                    default_a
+                ),
+                "display_labels = true (default: false, command line --labels)".to_string(),
+            ]
+        );
+
+        let shown: ViewerOptions = toml::from_str(&resolved.to_toml().unwrap()).unwrap();
+        assert_eq!(
+            shown.button_active_colors,
+            resolved.options.button_active_colors
+        );
+        assert!(shown.display_labels);
+    }
+
+    const PROFILES: &str = "display_labels = false\n\
+        [button_active_colors]\n\
+        a = \"#FF0000\"\n\
+        [profiles.practice]\n\
+        colored_rims = true\n\
+        [profiles.stream]\n\
+        display_labels = true\n\
+        button_active_colors = { a = \"#00FF00\" }";
+
+    #[test]
+    fn profiles_override_the_file() {
+        let mut config = LayeredConfig {
+            file: file_layer(&format!("profile = \"stream\"\n{}", PROFILES)),
+            ..Default::default()
+        };
+        assert_eq!(config.profile_names(), ["practice", "stream"]);
+        assert_eq!(config.active_profile().as_deref(), Some("stream"));
+
+        let resolved = config.resolve().unwrap();
+        assert!(resolved.options.display_labels);
+        assert!(!resolved.options.colored_rims);
+        assert_eq!(
+            resolved.options.button_active_colors.a,
+            "#00FF00".parse().unwrap()
+        );
+        assert_eq!(resolved.options.profile.as_deref(), Some("stream"));
+        assert_eq!(
+            resolved.source_of("display_labels"),
+            &ConfigSource::Profile {
+                name: "stream".into(),
+                path: "b0xx_viewer_config.toml".into()
+            }
+        );
+
+        // The command line picks another profile, the hotkeys pick over both
+        config.cli.set(
+            "profile",
+            toml::Value::String("practice".into()),
+            ConfigSource::Cli("--profile".into()),
+        );
+        let options = config.resolve().unwrap().options;
+        assert!(!options.display_labels && options.colored_rims);
+        config.select_profile(None);
+        assert_eq!(config.active_profile(), None);
+        let options = config.resolve().unwrap().options;
+        assert!(!options.display_labels && !options.colored_rims);
+        assert_eq!(options.button_active_colors.a, "#FF0000".parse().unwrap());
+    }
+
+    #[test]
+    fn unknown_profiles_are_ignored() {
+        let config = LayeredConfig {
+            file: file_layer(&format!("profile = \"strem\"\n{}", PROFILES)),
+            ..Default::default()
+        };
+        assert_eq!(config.active_profile(), None);
+        assert_eq!(config.unknown_profile(), Some("strem"));
+        let options = config.resolve().unwrap().options;
+        assert!(!options.display_labels);
+        assert_eq!(options.profile, None);
+
+        let config = LayeredConfig {
+            file: file_layer(&format!("profile = \"\"\n{}", PROFILES)),
+            ..Default::default()
+        };
+        assert_eq!(config.active_profile(), None);
+        assert_eq!(config.unknown_profile(), None);
+    }
+}
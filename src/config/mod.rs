@@ -0,0 +1,1196 @@
+mod contrast;
+mod layers;
+mod schema;
+mod theme;
+mod watch;
+
+pub use self::contrast::*;
+pub use self::layers::*;
+pub use self::schema::*;
+pub use self::theme::*;
+pub use self::watch::*;
+
+use crate::b0xx_state::B0xxModel;
+use crate::error::ViewerError;
+use crate::serial_probe::{
+    DetectionPolicy, ModelFingerprint, ProbeOptions, SerialLinkOptions, UsbRule,
+};
+use conrod_core::Color;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("The supplied config path could not be found on the filesystem.")]
+    NotFound,
+    #[error("DeserializationError: {0}")]
+    DeserializationError(#[from] toml::de::Error),
+    #[error("SerializationError: {0}")]
+    SerializationError(#[from] toml::ser::Error),
+    #[error("Invalid configuration version: {0}")]
+    InvalidVersion(String),
+    #[error("Invalid profile `{0}`: {1}")]
+    InvalidProfile(String, String),
+    #[error("Invalid theme code, {0}")]
+    InvalidThemeCode(String),
+}
+
+pub const DEFAULT_FILENAME: &str = "b0xx_viewer_config.toml";
+/// Environment variable pointing to the configuration file to use, bypassing the search path
+pub const CONFIG_PATH_ENV: &str = "B0XX_VIEWER_CONFIG_PATH";
+const CONFIG_DIR_NAME: &str = "b0xx_viewer";
+
+/// Bounds of the `scale` option
+pub const MIN_SCALE: f64 = 0.25;
+pub const MAX_SCALE: f64 = 8.;
+
+lazy_static! {
+    pub static ref DEFAULT_ACTIVE_COLOR: ViewerColor = ViewerColor(rgb::RGBA8::new(0, 235, 255, 255));
+    pub static ref DEFAULT_INACTIVE_COLOR: ViewerColor = ViewerColor(rgb::RGBA8::new(85, 87, 83, 255));
+    pub static ref DEFAULT_BACKGROUND_COLOR: ViewerColor = ViewerColor(rgb::RGBA8::new(19, 19, 19, 255));
+}
+
+/// Color names accepted in place of a hex code
+const NAMED_COLORS: &[(&str, [u8; 4])] = &[
+    ("transparent", [0, 0, 0, 0]),
+    ("black", [0, 0, 0, 255]),
+    ("white", [255, 255, 255, 255]),
+    ("gray", [128, 128, 128, 255]),
+    ("grey", [128, 128, 128, 255]),
+    ("red", [255, 0, 0, 255]),
+    ("green", [0, 128, 0, 255]),
+    ("lime", [0, 255, 0, 255]),
+    ("blue", [0, 0, 255, 255]),
+    ("yellow", [255, 255, 0, 255]),
+    ("cyan", [0, 255, 255, 255]),
+    ("magenta", [255, 0, 255, 255]),
+    ("orange", [255, 165, 0, 255]),
+    ("purple", [128, 0, 128, 255]),
+    ("pink", [255, 192, 203, 255]),
+];
+
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+pub enum ViewerColorType {
+    Active,
+    Inactive,
+    Background,
+}
+
+/// An RGBA color. Written as `"#RRGGBB"`, `"#RRGGBBAA"`, `"#RGB"` or a color name in configuration files,
+/// `{ r, g, b, a }` tables are still accepted with `a` defaulting to opaque
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ViewerColor(rgb::RGBA8);
+
+impl ViewerColor {
+    fn inactive_default() -> Self {
+        *DEFAULT_INACTIVE_COLOR
+    }
+
+    fn background_default() -> Self {
+        *DEFAULT_BACKGROUND_COLOR
+    }
+
+    /// The color `t` of the way from this one to `other`, `t` going from 0 to 1
+    pub fn mix(self, other: ViewerColor, t: f64) -> Self {
+        let t = t.clamp(0., 1.);
+        let blend = |from: u8, to: u8| (from as f64 + (to as f64 - from as f64) * t).round() as u8;
+        ViewerColor(rgb::RGBA8::new(
+            blend(self.0.r, other.0.r),
+            blend(self.0.g, other.0.g),
+            blend(self.0.b, other.0.b),
+            blend(self.0.a, other.0.a),
+        ))
+    }
+
+    /// This color with its alpha multiplied by `opacity`, from 0 to 1
+    pub fn with_opacity(self, opacity: f64) -> Self {
+        let a = (self.0.a as f64 * opacity.clamp(0., 1.)).round() as u8;
+        ViewerColor(rgb::RGBA8::new(self.0.r, self.0.g, self.0.b, a))
+    }
+
+    pub fn to_hex(self) -> String {
+        let rgba = self.0;
+        if rgba.a == 255 {
+            format!("#{:02X}{:02X}{:02X}", rgba.r, rgba.g, rgba.b)
+        } else {
+            format!("#{:02X}{:02X}{:02X}{:02X}", rgba.r, rgba.g, rgba.b, rgba.a)
+        }
+    }
+}
+
+impl Default for ViewerColor {
+    fn default() -> Self {
+        Self::inactive_default()
+    }
+}
+
+impl std::str::FromStr for ViewerColor {
+    type Err = String;
+
+    /// Parses colors in hex format, eg. "#00FF00", "#00FF0080" or "#0F0", or a color name, eg. "white"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some((_, [r, g, b, a])) = NAMED_COLORS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+        {
+            return Ok(ViewerColor(rgb::RGBA8::new(*r, *g, *b, *a)));
+        }
+
+        let invalid = || {
+            format!(
+                "Invalid color \"{}\", expected #RRGGBB, #RRGGBBAA, #RGB or a color name",
+                s
+            )
+        };
+
+        let hex = s.trim_start_matches('#');
+        // Shorthand colors repeat every digit, "#0F8" is "#00FF88"
+        let hex = if hex.len() == 3 {
+            hex.chars().flat_map(|c| [c, c]).collect()
+        } else {
+            hex.to_string()
+        };
+        let hex = hex.as_str();
+        if (hex.len() != 6 && hex.len() != 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+
+        let value = u32::from_str_radix(hex, 16).map_err(|_| invalid())?;
+        let value = if hex.len() == 6 {
+            (value << 8) | 255
+        } else {
+            value
+        };
+
+        Ok(ViewerColor(rgb::RGBA8::new(
+            ((value >> 24) & 255) as u8,
+            ((value >> 16) & 255) as u8,
+            ((value >> 8) & 255) as u8,
+            (value & 255) as u8,
+        )))
+    }
+}
+
+impl Serialize for ViewerColor {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+#[derive(Deserialize)]
+struct ViewerColorTable {
+    r: u8,
+    g: u8,
+    b: u8,
+    #[serde(default = "ViewerColorTable::opaque")]
+    a: u8,
+}
+
+impl ViewerColorTable {
+    fn opaque() -> u8 {
+        255
+    }
+}
+
+impl<'de> Deserialize<'de> for ViewerColor {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ViewerColorVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for ViewerColorVisitor {
+            type Value = ViewerColor;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a \"#RRGGBB\", \"#RRGGBBAA\" or \"#RGB\" string, a color name or an { r, g, b, a } table")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                value.parse().map_err(E::custom)
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let table = ViewerColorTable::deserialize(
+                    serde::de::value::MapAccessDeserializer::new(map),
+                )?;
+                Ok(ViewerColor(rgb::RGBA8::new(table.r, table.g, table.b, table.a)))
+            }
+        }
+
+        deserializer.deserialize_any(ViewerColorVisitor)
+    }
+}
+
+impl From<Color> for ViewerColor {
+    fn from(value: Color) -> Self {
+        let (r, g, b, a) = (
+            (value.red() * 255.).ceil() as u8,
+            (value.green() * 255.).ceil() as u8,
+            (value.blue() * 255.).ceil() as u8,
+            (value.to_rgb().3 * 255.).ceil() as u8,
+        );
+
+        ViewerColor(rgb::RGBA8::new(r, g, b, a))
+    }
+}
+
+impl From<ViewerColor> for Color {
+    fn from(value: ViewerColor) -> Self {
+        Color::Rgba(
+            value.0.r as f32 / 255.,
+            value.0.g as f32 / 255.,
+            value.0.b as f32 / 255.,
+            value.0.a as f32 / 255.,
+        )
+    }
+}
+
+impl std::ops::Deref for ViewerColor {
+    type Target = rgb::RGBA8;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ViewerButtonColors {
+    #[serde(default)]
+    pub start: ViewerColor,
+    #[serde(default)]
+    pub y: ViewerColor,
+    #[serde(default)]
+    pub x: ViewerColor,
+    #[serde(default)]
+    pub b: ViewerColor,
+    #[serde(default)]
+    pub a: ViewerColor,
+    #[serde(default)]
+    pub l: ViewerColor,
+    #[serde(default)]
+    pub r: ViewerColor,
+    #[serde(default)]
+    pub z: ViewerColor,
+    #[serde(default)]
+    pub up: ViewerColor,
+    #[serde(default)]
+    pub down: ViewerColor,
+    #[serde(default)]
+    pub right: ViewerColor,
+    #[serde(default)]
+    pub left: ViewerColor,
+    #[serde(default)]
+    pub mod_x: ViewerColor,
+    #[serde(default)]
+    pub mod_y: ViewerColor,
+    #[serde(default)]
+    pub c_left: ViewerColor,
+    #[serde(default)]
+    pub c_right: ViewerColor,
+    #[serde(default)]
+    pub c_up: ViewerColor,
+    #[serde(default)]
+    pub c_down: ViewerColor,
+    #[serde(default)]
+    pub mod_ls: ViewerColor,
+    #[serde(default)]
+    pub mod_ms: ViewerColor,
+}
+
+impl ViewerButtonColors {
+    fn default_inactive() -> Self {
+        Self::new_with_color(*DEFAULT_INACTIVE_COLOR)
+    }
+
+    fn default_active() -> Self {
+        Self::new_with_color(*DEFAULT_ACTIVE_COLOR)
+    }
+
+    /// Colors in [`BUTTON_NAMES`] order
+    pub fn to_array(self) -> [ViewerColor; 20] {
+        [
+            self.start,
+            self.y,
+            self.x,
+            self.b,
+            self.a,
+            self.l,
+            self.r,
+            self.z,
+            self.up,
+            self.down,
+            self.right,
+            self.left,
+            self.mod_x,
+            self.mod_y,
+            self.c_left,
+            self.c_right,
+            self.c_up,
+            self.c_down,
+            self.mod_ls,
+            self.mod_ms,
+        ]
+    }
+
+    pub fn from_array(colors: [ViewerColor; 20]) -> Self {
+        let [
+            start, y, x, b, a, l, r, z, up, down, right, left, mod_x, mod_y, c_left, c_right, c_up,
+            c_down, mod_ls, mod_ms,
+        ] = colors;
+        Self {
+            start,
+            y,
+            x,
+            b,
+            a,
+            l,
+            r,
+            z,
+            up,
+            down,
+            right,
+            left,
+            mod_x,
+            mod_y,
+            c_left,
+            c_right,
+            c_up,
+            c_down,
+            mod_ls,
+            mod_ms,
+        }
+    }
+
+    pub fn new_with_color(color: ViewerColor) -> Self {
+        Self {
+            start: color,
+            y: color,
+            x: color,
+            b: color,
+            a: color,
+            l: color,
+            r: color,
+            z: color,
+            up: color,
+            down: color,
+            right: color,
+            left: color,
+            mod_x: color,
+            mod_y: color,
+            c_left: color,
+            c_right: color,
+            c_up: color,
+            c_down: color,
+            mod_ls: color,
+            mod_ms: color,
+        }
+    }
+}
+
+/// Names of the B0XX buttons as used in configuration tables and command line overrides
+pub const BUTTON_NAMES: [&str; 20] = [
+    "start", "y", "x", "b", "a", "l", "r", "z", "up", "down", "right", "left", "mod_x", "mod_y",
+    "c_left", "c_right", "c_up", "c_down", "mod_ls", "mod_ms",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct ViewerButtonLabels {
+    pub start: String,
+    pub y: String,
+    pub x: String,
+    pub b: String,
+    pub a: String,
+    pub l: String,
+    pub r: String,
+    pub z: String,
+    pub up: String,
+    pub down: String,
+    pub right: String,
+    pub left: String,
+    pub mod_x: String,
+    pub mod_y: String,
+    pub c_left: String,
+    pub c_right: String,
+    pub c_up: String,
+    pub c_down: String,
+    pub mod_ls: String,
+    pub mod_ms: String,
+}
+
+impl ViewerButtonLabels {
+    /// Labels in [`BUTTON_NAMES`] order
+    pub fn to_array(&self) -> [&str; 20] {
+        [
+            &self.start,
+            &self.y,
+            &self.x,
+            &self.b,
+            &self.a,
+            &self.l,
+            &self.r,
+            &self.z,
+            &self.up,
+            &self.down,
+            &self.right,
+            &self.left,
+            &self.mod_x,
+            &self.mod_y,
+            &self.c_left,
+            &self.c_right,
+            &self.c_up,
+            &self.c_down,
+            &self.mod_ls,
+            &self.mod_ms,
+        ]
+    }
+
+    pub fn from_array(labels: [String; 20]) -> Self {
+        let [
+            start, y, x, b, a, l, r, z, up, down, right, left, mod_x, mod_y, c_left, c_right, c_up,
+            c_down, mod_ls, mod_ms,
+        ] = labels;
+        Self {
+            start,
+            y,
+            x,
+            b,
+            a,
+            l,
+            r,
+            z,
+            up,
+            down,
+            right,
+            left,
+            mod_x,
+            mod_y,
+            c_left,
+            c_right,
+            c_up,
+            c_down,
+            mod_ls,
+            mod_ms,
+        }
+    }
+}
+
+impl Default for ViewerButtonLabels {
+    fn default() -> Self {
+        Self {
+            start: "SRT".into(),
+            y: "Y".into(),
+            x: "X".into(),
+            b: "B".into(),
+            a: "A".into(),
+            l: "L".into(),
+            r: "R".into(),
+            z: "Z".into(),
+            up: "↑".into(),
+            down: "↓".into(),
+            right: "→".into(),
+            left: "←".into(),
+            mod_x: "MX".into(),
+            mod_y: "MY".into(),
+            c_left: "CL".into(),
+            c_right: "CR".into(),
+            c_up: "CU".into(),
+            c_down: "CD".into(),
+            mod_ls: "LS".into(),
+            mod_ms: "MS".into(),
+        }
+    }
+}
+
+/// Images drawn in place of a button's circle
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewerButtonSkin {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pressed: Option<std::path::PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub released: Option<std::path::PathBuf>,
+}
+
+/// Images replacing the flat background and circles. Relative paths start from the folder of the
+/// configuration file
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewerSkin {
+    /// Drawn behind the buttons, stretched over the layout
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background: Option<std::path::PathBuf>,
+    /// Images of the buttons without their own in `buttons`
+    #[serde(flatten)]
+    pub default: ViewerButtonSkin,
+    /// Images of single buttons, by button name
+    #[serde(skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub buttons: std::collections::BTreeMap<String, ViewerButtonSkin>,
+}
+
+impl ViewerSkin {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// The pressed and released images of a button, falling back to the default ones
+    pub fn button(&self, name: &str) -> (Option<&std::path::Path>, Option<&std::path::Path>) {
+        let button = self.buttons.get(name);
+        let pick = |image: fn(&ViewerButtonSkin) -> &Option<std::path::PathBuf>| {
+            button
+                .and_then(|button| image(button).as_deref())
+                .or_else(|| image(&self.default).as_deref())
+        };
+        (pick(|skin| &skin.pressed), pick(|skin| &skin.released))
+    }
+
+    /// Every image the skin refers to
+    pub fn images(&self) -> impl Iterator<Item = &std::path::Path> {
+        self.background
+            .iter()
+            .chain(std::iter::once(&self.default).chain(self.buttons.values()).flat_map(
+                |button| button.pressed.iter().chain(button.released.iter()),
+            ))
+            .map(std::path::PathBuf::as_path)
+    }
+}
+
+/// Where a label is drawn relative to its button
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelPosition {
+    #[default]
+    Inside,
+    Above,
+    Below,
+    Hidden,
+}
+
+/// Typeface of the button labels
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewerFont {
+    /// TrueType or OpenType file, the embedded Alata font is used when unset or unreadable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<std::path::PathBuf>,
+    /// Size of every label, at the base window size
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u32>,
+    /// Sizes of single labels, by button name
+    #[serde(skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub sizes: std::collections::BTreeMap<String, u32>,
+}
+
+impl ViewerFont {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Size of a button's label, `default` if none was configured
+    pub fn label_size(&self, name: &str, default: u32) -> u32 {
+        self.sizes
+            .get(name)
+            .copied()
+            .or(self.size)
+            .unwrap_or(default)
+    }
+}
+
+/// Kinds of outline a button can be drawn with
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ShapeKind {
+    #[default]
+    Circle,
+    Square,
+    Ellipse,
+    RoundedRectangle,
+    Polygon,
+}
+
+/// Outline of a button, in units of the base layout. Written either as a kind, `"square"`, or as
+/// a table, `{ type = "ellipse", width = 48, height = 32 }`
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct ButtonShape {
+    #[serde(rename = "type")]
+    pub kind: ShapeKind,
+    /// Width and height of the shape, unless `width` or `height` are given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<f64>,
+    /// Corner radius of rounded rectangles
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub radius: Option<f64>,
+    /// Corners of polygons, relative to the center of the button with y pointing up
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub points: Vec<[f64; 2]>,
+}
+
+#[derive(Deserialize)]
+struct ButtonShapeTable {
+    #[serde(rename = "type")]
+    kind: ShapeKind,
+    #[serde(default)]
+    size: Option<f64>,
+    #[serde(default)]
+    width: Option<f64>,
+    #[serde(default)]
+    height: Option<f64>,
+    #[serde(default)]
+    radius: Option<f64>,
+    #[serde(default)]
+    points: Vec<[f64; 2]>,
+}
+
+impl<'de> Deserialize<'de> for ButtonShape {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ButtonShapeVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for ButtonShapeVisitor {
+            type Value = ButtonShape;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a shape name or a { type, size, width, height, radius, points } table")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                use serde::de::IntoDeserializer;
+                let kind = ShapeKind::deserialize(value.into_deserializer())?;
+                if kind == ShapeKind::Polygon {
+                    return Err(E::custom("a polygon needs a table with its points"));
+                }
+                Ok(ButtonShape {
+                    kind,
+                    ..Default::default()
+                })
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                use serde::de::Error;
+                let table = ButtonShapeTable::deserialize(
+                    serde::de::value::MapAccessDeserializer::new(map),
+                )?;
+                if table.kind == ShapeKind::Polygon && table.points.len() < 3 {
+                    return Err(A::Error::custom("a polygon needs at least 3 points"));
+                }
+                Ok(ButtonShape {
+                    kind: table.kind,
+                    size: table.size,
+                    width: table.width,
+                    height: table.height,
+                    radius: table.radius,
+                    points: table.points,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(ButtonShapeVisitor)
+    }
+}
+
+impl ButtonShape {
+    /// Width and height of the shape, `default` standing for the size of a circle button.
+    /// Circles and squares ignore `width` and `height`, polygons are measured from their points
+    pub fn dimensions(&self, default: f64) -> [f64; 2] {
+        let size = self.size.unwrap_or(default);
+        match self.kind {
+            ShapeKind::Circle | ShapeKind::Square => [size, size],
+            ShapeKind::Ellipse | ShapeKind::RoundedRectangle => [
+                self.width.unwrap_or(size),
+                self.height.unwrap_or(size),
+            ],
+            ShapeKind::Polygon => {
+                let extent = |axis: usize| {
+                    let values = self.points.iter().map(|point| point[axis]);
+                    values.clone().fold(f64::MIN, f64::max) - values.fold(f64::MAX, f64::min)
+                };
+                [extent(0), extent(1)]
+            }
+        }
+    }
+
+    /// Corner radius of a rounded rectangle, a fifth of its smallest side unless configured
+    pub fn corner_radius(&self, default: f64) -> f64 {
+        let [w, h] = self.dimensions(default);
+        self.radius
+            .unwrap_or(w.min(h) / 5.)
+            .clamp(0., w.min(h) / 2.)
+    }
+}
+
+/// Transitions played when buttons change state. Durations are in milliseconds, 0 turns an
+/// effect off
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewerAnimations {
+    /// Fades released buttons back to their inactive color
+    pub fade_out: u64,
+    /// Briefly enlarges buttons when they're pressed
+    pub press_pulse: u64,
+    /// How much larger a pulsing button gets at its peak, eg. 0.15 for 15%
+    pub pulse_scale: f64,
+    /// Fades a halo of the active color in around pressed buttons, and out once released
+    pub glow: u64,
+    /// Width of the halo, in units of the base layout
+    pub glow_size: f64,
+}
+
+impl Default for ViewerAnimations {
+    fn default() -> Self {
+        Self {
+            fade_out: 0,
+            press_pulse: 0,
+            pulse_scale: 0.15,
+            glow: 0,
+            glow_size: 8.,
+        }
+    }
+}
+
+impl ViewerAnimations {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// How long a button keeps animating after it changed state
+    pub fn longest(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.fade_out.max(self.press_pulse).max(self.glow))
+    }
+}
+
+/// A polygon drawn behind the buttons, eg. the outline of the controller's case
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewerCase {
+    /// Corners of the case, relative to the center of the layout with y pointing up
+    pub points: Vec<[f64; 2]>,
+    /// Fill of the case, left see-through when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<ViewerColor>,
+    /// Color of the case's outline, none is drawn when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outline: Option<ViewerColor>,
+}
+
+impl ViewerCase {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ViewerModelSelection {
+    #[default]
+    Auto,
+    R1,
+    R2,
+}
+
+impl std::str::FromStr for ViewerModelSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ViewerModelSelection::Auto),
+            "r1" => Ok(ViewerModelSelection::R1),
+            "r2" => Ok(ViewerModelSelection::R2),
+            _ => Err(format!("Unknown B0XX model \"{}\", expected auto, r1 or r2", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ViewerDetectionOptions {
+    #[serde(default)]
+    pub relax_arduino: bool,
+    #[serde(default)]
+    pub rules: Vec<UsbRule>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ViewerOptions {
+    #[serde(default = "ViewerOptions::current_version")]
+    pub version: u32,
+    #[serde(default)]
+    pub display_labels: bool,
+    #[serde(default)]
+    pub labels: ViewerButtonLabels,
+    #[serde(default)]
+    pub label_position: LabelPosition,
+    /// Positions of single labels, by button name
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub label_positions: std::collections::BTreeMap<String, LabelPosition>,
+    #[serde(default)]
+    pub chromeless: bool,
+    /// Size of the window when it opens, as a multiple of the base layout
+    #[serde(default = "ViewerOptions::default_scale")]
+    pub scale: f64,
+    /// Keeps the layout proportions when the window is resized instead of stretching it
+    #[serde(default = "ViewerOptions::default_keep_aspect_ratio")]
+    pub keep_aspect_ratio: bool,
+    /// Leaves the window background see-through so only the buttons are drawn. `background_color`
+    /// then stands for what the viewer is composited on, to pick label colors and check contrast
+    #[serde(default)]
+    pub transparent: bool,
+    #[serde(default = "ViewerColor::background_default")]
+    pub background_color: ViewerColor,
+    #[serde(default = "ViewerButtonColors::default_inactive")]
+    pub button_inactive_colors: ViewerButtonColors,
+    #[serde(default = "ViewerButtonColors::default_active")]
+    pub button_active_colors: ViewerButtonColors,
+    #[serde(default)]
+    pub model: ViewerModelSelection,
+    #[serde(default)]
+    pub model_fingerprints: Vec<ModelFingerprint>,
+    #[serde(default)]
+    pub colored_rims: bool,
+    #[serde(default)]
+    pub button_shape: ButtonShape,
+    /// Shapes of single buttons, by button name
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub button_shapes: std::collections::BTreeMap<String, ButtonShape>,
+    #[serde(default, skip_serializing_if = "ViewerCase::is_empty")]
+    pub case: ViewerCase,
+    #[serde(default, skip_serializing_if = "ViewerAnimations::is_empty")]
+    pub animation: ViewerAnimations,
+    #[serde(default, skip_serializing_if = "ViewerSkin::is_empty")]
+    pub skin: ViewerSkin,
+    #[serde(default, skip_serializing_if = "ViewerFont::is_empty")]
+    pub font: ViewerFont,
+    pub custom_tty: Option<String>,
+    #[serde(default)]
+    pub detection: ViewerDetectionOptions,
+    #[serde(default)]
+    pub serial: SerialLinkOptions,
+    #[serde(default)]
+    pub single_instance: bool,
+    /// Name of the `[profiles.<name>]` table applied on top of the configuration file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Name of the bundled theme the configuration is based on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme: Option<String>,
+    #[serde(skip)]
+    path: std::path::PathBuf,
+}
+
+impl Default for ViewerOptions {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            display_labels: false,
+            labels: ViewerButtonLabels::default(),
+            label_position: LabelPosition::default(),
+            label_positions: Default::default(),
+            chromeless: false,
+            scale: Self::default_scale(),
+            keep_aspect_ratio: Self::default_keep_aspect_ratio(),
+            transparent: false,
+            background_color: *DEFAULT_BACKGROUND_COLOR,
+            button_inactive_colors: ViewerButtonColors::new_with_color(*DEFAULT_INACTIVE_COLOR),
+            button_active_colors: ViewerButtonColors::new_with_color(*DEFAULT_ACTIVE_COLOR),
+            custom_tty: None,
+            detection: ViewerDetectionOptions::default(),
+            serial: SerialLinkOptions::default(),
+            single_instance: false,
+            profile: None,
+            theme: None,
+            model: ViewerModelSelection::default(),
+            model_fingerprints: vec![],
+            colored_rims: false,
+            button_shape: ButtonShape::default(),
+            button_shapes: Default::default(),
+            case: ViewerCase::default(),
+            animation: ViewerAnimations::default(),
+            skin: ViewerSkin::default(),
+            font: ViewerFont::default(),
+            path: Default::default(),
+        }
+    }
+}
+
+impl ViewerOptions {
+    fn current_version() -> u32 {
+        CONFIG_VERSION
+    }
+
+    fn default_scale() -> f64 {
+        1.
+    }
+
+    fn default_keep_aspect_ratio() -> bool {
+        true
+    }
+
+    /// The `scale` option, brought back within [`MIN_SCALE`] and [`MAX_SCALE`]
+    pub fn window_scale(&self) -> f64 {
+        if self.scale.is_finite() {
+            self.scale.clamp(MIN_SCALE, MAX_SCALE)
+        } else {
+            Self::default_scale()
+        }
+    }
+
+    /// Resolves the layout to draw: an explicit choice always wins over what was detected on the device
+    pub fn effective_model(&self, detected: Option<B0xxModel>) -> B0xxModel {
+        match self.model {
+            ViewerModelSelection::R1 => B0xxModel::R1,
+            ViewerModelSelection::R2 => B0xxModel::R2,
+            ViewerModelSelection::Auto => detected.unwrap_or(B0xxModel::R1),
+        }
+    }
+
+    /// Where to draw the label of a button
+    pub fn label_position(&self, name: &str) -> LabelPosition {
+        self.label_positions
+            .get(name)
+            .copied()
+            .unwrap_or(self.label_position)
+    }
+
+    /// The shape to draw a button with
+    pub fn button_shape(&self, name: &str) -> &ButtonShape {
+        self.button_shapes.get(name).unwrap_or(&self.button_shape)
+    }
+
+    /// The configuration file of the current user, eg. `~/.config/b0xx_viewer/b0xx_viewer_config.toml`
+    /// on Linux or `%APPDATA%\b0xx_viewer\b0xx_viewer_config.toml` on Windows
+    pub fn user_config_path() -> Option<std::path::PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(CONFIG_DIR_NAME).join(DEFAULT_FILENAME))
+    }
+
+    /// Next to the executable, where configuration files used to live
+    pub fn exe_config_path() -> Result<std::path::PathBuf, ViewerError> {
+        let mut path = std::env::current_exe()?;
+        path.set_file_name(DEFAULT_FILENAME);
+        Ok(path)
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn system_config_paths() -> Vec<std::path::PathBuf> {
+        let dirs = std::env::var("XDG_CONFIG_DIRS")
+            .ok()
+            .filter(|dirs| !dirs.is_empty())
+            .unwrap_or_else(|| "/etc/xdg".into());
+
+        std::env::split_paths(&dirs)
+            .filter(|dir| dir.is_absolute())
+            .map(|dir| dir.join(CONFIG_DIR_NAME).join(DEFAULT_FILENAME))
+            .collect()
+    }
+
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    fn system_config_paths() -> Vec<std::path::PathBuf> {
+        vec![]
+    }
+
+    /// Where configuration files are looked for, in order: the user config directory,
+    /// the system config directories (`$XDG_CONFIG_DIRS`) then the executable's folder
+    pub fn search_paths() -> Vec<std::path::PathBuf> {
+        Self::user_config_path()
+            .into_iter()
+            .chain(Self::system_config_paths())
+            .chain(Self::exe_config_path().ok())
+            .collect()
+    }
+
+    /// The configuration file set through `B0XX_VIEWER_CONFIG_PATH`, if any
+    pub fn config_path_override() -> Option<std::path::PathBuf> {
+        std::env::var_os(CONFIG_PATH_ENV)
+            .filter(|path| !path.is_empty())
+            .map(Into::into)
+    }
+
+    /// The first existing configuration file on the search path
+    pub fn find_config() -> Option<std::path::PathBuf> {
+        Self::search_paths().into_iter().find(|path| path.is_file())
+    }
+
+    /// Whether the serial connection settings differ, those only apply when connecting
+    pub fn probe_settings_differ(&self, other: &Self) -> bool {
+        self.custom_tty != other.custom_tty
+            || self.detection != other.detection
+            || self.serial != other.serial
+            || self.model_fingerprints != other.model_fingerprints
+            || self.single_instance != other.single_instance
+    }
+
+    pub fn detection_policy(&self) -> DetectionPolicy {
+        DetectionPolicy::new(
+            self.custom_tty.clone(),
+            self.detection.relax_arduino,
+            &self.detection.rules,
+            &self.model_fingerprints,
+        )
+    }
+
+    pub fn probe_options(&self) -> ProbeOptions {
+        ProbeOptions {
+            detection: self.detection_policy(),
+            link: self.serial,
+            capture: None,
+            single_instance: self.single_instance,
+        }
+    }
+
+    pub fn save_to(&mut self, path: std::path::PathBuf) -> Result<(), ViewerError> {
+        // Going through a `toml::Value` writes plain values before tables, whatever the field order
+        let buf = toml::Value::try_from(&*self)
+            .and_then(|value| toml::ser::to_vec(&value))
+            .map_err(|e| ViewerError::from(ConfigError::SerializationError(e)))?;
+        std::fs::write(path.clone(), buf)?;
+        self.path = path;
+        Ok(())
+    }
+
+    /// Saves to the user config directory, or next to the executable when there's none
+    pub fn save_user_config(&mut self) -> Result<(), ViewerError> {
+        let path = match Self::user_config_path() {
+            Some(path) => path,
+            None => Self::exe_config_path()?,
+        };
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        self.save_to(path)
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// Resolves a path found in the configuration, relative ones start from the configuration's folder
+    pub fn asset_path(&self, path: &std::path::Path) -> std::path::PathBuf {
+        match self.path.parent() {
+            Some(dir) if path.is_relative() => dir.join(path),
+            _ => path.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_scale() {
+        let options = ViewerOptions::default();
+        assert_eq!(options.window_scale(), 1.);
+        assert!(options.keep_aspect_ratio);
+
+        let scaled = |scale: f64| ViewerOptions { scale, ..Default::default() }.window_scale();
+        assert_eq!(scaled(2.5), 2.5);
+        assert_eq!(scaled(0.), MIN_SCALE);
+        assert_eq!(scaled(100.), MAX_SCALE);
+        assert_eq!(scaled(f64::NAN), 1.);
+        assert_eq!(scaled(f64::INFINITY), 1.);
+    }
+
+    #[test]
+    fn label_positions() {
+        let mut options: ViewerOptions =
+            toml::from_str("label_position = \"below\"\n[label_positions]\nstart = \"hidden\"").unwrap();
+        assert_eq!(options.label_position("a"), LabelPosition::Below);
+        assert_eq!(options.label_position("start"), LabelPosition::Hidden);
+
+        options.label_positions.clear();
+        assert_eq!(options.label_position("start"), LabelPosition::Below);
+        assert_eq!(ViewerOptions::default().label_position("a"), LabelPosition::Inside);
+        assert!(toml::from_str::<ViewerOptions>("label_position = \"left\"").is_err());
+    }
+
+    #[test]
+    fn button_shapes() {
+        let shape = |text: &str| toml::from_str::<ViewerOptions>(text).map(|options| options.button_shape);
+
+        assert_eq!(ViewerOptions::default().button_shape("a").dimensions(40.), [40., 40.]);
+        assert_eq!(shape("button_shape = \"square\"").unwrap().dimensions(40.), [40., 40.]);
+        let ellipse = shape("button_shape = { type = \"ellipse\", width = 48, size = 30 }").unwrap();
+        assert_eq!(ellipse.dimensions(40.), [48., 30.]);
+
+        let rounded = shape("button_shape = \"rounded_rectangle\"").unwrap();
+        assert_eq!(rounded.corner_radius(40.), 8.);
+        let rounded = shape("button_shape = { type = \"rounded_rectangle\", radius = 50 }").unwrap();
+        assert_eq!(rounded.corner_radius(40.), 20.);
+
+        let polygon = "button_shape = { type = \"polygon\", points = [[0, 10], [10, -5], [-10, -5]] }";
+        assert_eq!(shape(polygon).unwrap().dimensions(40.), [20., 15.]);
+        assert!(shape("button_shape = \"polygon\"").is_err());
+        assert!(shape("button_shape = { type = \"polygon\", points = [[0, 0], [1, 1]] }").is_err());
+        assert!(shape("button_shape = \"star\"").is_err());
+
+        let options: ViewerOptions =
+            toml::from_str("button_shape = \"square\"\n[button_shapes]\na = \"ellipse\"").unwrap();
+        assert_eq!(options.button_shape("a").kind, ShapeKind::Ellipse);
+        assert_eq!(options.button_shape("b").kind, ShapeKind::Square);
+    }
+
+    #[test]
+    fn animations() {
+        assert_eq!(ViewerOptions::default().animation.longest(), std::time::Duration::ZERO);
+        let animation: ViewerAnimations = toml::from_str("fade_out = 200\nglow = 350").unwrap();
+        assert_eq!(animation.longest(), std::time::Duration::from_millis(350));
+        assert_eq!(animation.pulse_scale, 0.15);
+        assert!(!animation.is_empty());
+    }
+
+    #[test]
+    fn colors_in_toml() {
+        let rgba = |r, g, b, a| ViewerColor(rgb::RGBA8::new(r, g, b, a));
+        let options: ViewerOptions = toml::from_str(
+            "background_color = \"#00EBFF80\"\n\
+            [button_active_colors]\n\
+            a = \"#00ebff\"\n\
+            b = \"#0F8\"\n\
+            x = \"Orange\"\n\
+            y = \"transparent\"\n\
+            z = { r = 1, g = 2, b = 3 }\n\
+            l = { r = 1, g = 2, b = 3, a = 4 }",
+        )
+        .unwrap();
+        assert_eq!(options.background_color, rgba(0, 235, 255, 128));
+        let colors = &options.button_active_colors;
+        assert_eq!(colors.a, rgba(0, 235, 255, 255));
+        assert_eq!(colors.b, rgba(0, 255, 136, 255));
+        assert_eq!(colors.x, rgba(255, 165, 0, 255));
+        assert_eq!(colors.y.a, 0);
+        assert_eq!(colors.z, rgba(1, 2, 3, 255));
+        assert_eq!(colors.l, rgba(1, 2, 3, 4));
+
+        // Colors are written back as hex, alpha only when it isn't opaque
+        let written = toml::Value::try_from(&options).unwrap();
+        assert_eq!(written["background_color"].as_str(), Some("#00EBFF80"));
+        assert_eq!(written["button_active_colors"]["z"].as_str(), Some("#010203"));
+        assert_eq!(written["button_active_colors"]["l"].as_str(), Some("#01020304"));
+        let reread: ViewerOptions = written.try_into().unwrap();
+        assert_eq!(reread.background_color, options.background_color);
+        assert_eq!(reread.button_active_colors, options.button_active_colors);
+
+        for invalid in [
+            "\"#00EBF\"",
+            "\"#GGGGGG\"",
+            "\"not a color\"",
+            "{ r = 1, g = 2 }",
+            "{ r = 256, g = 0, b = 0 }",
+        ] {
+            let text = format!("background_color = {}", invalid);
+            assert!(toml::from_str::<ViewerOptions>(&text).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn color_mixing() {
+        let black: ViewerColor = "black".parse().unwrap();
+        let white: ViewerColor = "white".parse().unwrap();
+        assert_eq!(black.mix(white, 0.), black);
+        assert_eq!(black.mix(white, 0.5), "#808080".parse().unwrap());
+        assert_eq!(black.mix(white, 2.), white);
+        assert_eq!(white.with_opacity(0.), "#FFFFFF00".parse().unwrap());
+        assert_eq!(white.with_opacity(0.5), "#FFFFFF80".parse().unwrap());
+    }
+}
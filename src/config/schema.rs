@@ -0,0 +1,533 @@
+use super::{ConfigError, ViewerColor, ViewerOptions};
+use crate::b0xx_state::B0xxModel;
+use crate::error::ViewerError;
+use crate::serial_probe::{ModelFingerprint, UsbRule};
+use lazy_static::lazy_static;
+
+/// Version of the configuration file format written by this build.
+/// Files without a `version` key predate versioning and are considered version 1
+pub const CONFIG_VERSION: u32 = 2;
+
+type Migration = fn(&mut toml::value::Table);
+
+/// `MIGRATIONS[n]` upgrades a file from version `n + 1` to `n + 2`
+const MIGRATIONS: &[Migration] = &[migrate_v1_to_v2];
+
+/// v2 replaced `is_r2_b0xx` with `model` and writes colors as hex strings
+fn migrate_v1_to_v2(values: &mut toml::value::Table) {
+    if let Some(is_r2) = values.remove("is_r2_b0xx") {
+        if is_r2.as_bool() == Some(true) && !values.contains_key("model") {
+            values.insert("model".into(), toml::Value::String("r2".into()));
+        }
+    }
+
+    if let Some(color) = values.get_mut("background_color") {
+        color_table_to_hex(color);
+    }
+
+    for key in ["button_inactive_colors", "button_active_colors"] {
+        if let Some(toml::Value::Table(colors)) = values.get_mut(key) {
+            for (_, color) in colors.iter_mut() {
+                color_table_to_hex(color);
+            }
+        }
+    }
+}
+
+fn color_table_to_hex(value: &mut toml::Value) {
+    if !value.is_table() {
+        return;
+    }
+
+    if let Ok(color) = value.clone().try_into::<ViewerColor>() {
+        *value = toml::Value::String(color.to_hex());
+    }
+}
+
+/// Reads the schema version of a configuration file
+pub fn file_version(values: &toml::value::Table) -> Result<u32, ViewerError> {
+    match values.get("version") {
+        None => Ok(1),
+        Some(toml::Value::Integer(version)) if *version >= 1 => Ok(*version as u32),
+        Some(other) => Err(ConfigError::InvalidVersion(other.to_string()).into()),
+    }
+}
+
+/// Upgrades `values` to [`CONFIG_VERSION`] in place. Returns the version the file was in
+/// when a migration happened
+pub fn migrate(values: &mut toml::value::Table) -> Result<Option<u32>, ViewerError> {
+    let version = file_version(values)?;
+    if version > CONFIG_VERSION {
+        warn!(
+            "The configuration was written by a newer b0xx_viewer (version {}, this build supports {}), some settings may be ignored",
+            version, CONFIG_VERSION
+        );
+        return Ok(None);
+    }
+
+    if version == CONFIG_VERSION {
+        return Ok(None);
+    }
+
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        migration(values);
+    }
+
+    values.insert(
+        "version".into(),
+        toml::Value::Integer(CONFIG_VERSION as i64),
+    );
+
+    Ok(Some(version))
+}
+
+/// Whether loading a configuration file written by an older release may update the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileMigration {
+    /// Rewrites the file, keeping a backup of the original
+    Rewrite,
+    /// Leaves the file alone, eg. when only inspecting it or while it's open in an editor
+    InMemory,
+}
+
+/// Migrates the configuration file at `path` if needed. With [`FileMigration::Rewrite`], the file
+/// is updated and a copy of the original is kept next to it as `<name>.v<version>.bak`
+pub fn migrate_file(
+    path: &std::path::Path,
+    values: &mut toml::value::Table,
+    migration: FileMigration,
+) -> Result<(), ViewerError> {
+    let from = match migrate(values)? {
+        Some(from) => from,
+        None => return Ok(()),
+    };
+
+    if migration == FileMigration::InMemory {
+        warn!(
+            "{} is in version {}, it was upgraded to {} in memory only and the file was left unchanged",
+            path.display(),
+            from,
+            CONFIG_VERSION
+        );
+        return Ok(());
+    }
+
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".v{}.bak", from));
+    let backup = std::path::PathBuf::from(backup);
+
+    let written = std::fs::copy(path, &backup)
+        .map_err(ViewerError::from)
+        .and_then(|_| {
+            toml::ser::to_vec(&toml::Value::Table(values.clone()))
+                .map_err(|e| ViewerError::from(ConfigError::SerializationError(e)))
+        })
+        .and_then(|buf| std::fs::write(path, buf).map_err(ViewerError::from));
+
+    match written {
+        Ok(()) => warn!(
+            "Migrated {} from version {} to {}. Comments and key order were not kept, the original was saved as {}",
+            path.display(),
+            from,
+            CONFIG_VERSION,
+            backup.display()
+        ),
+        Err(e) => warn!(
+            "Migrated {} from version {} to {} in memory only, the file could not be updated: {}",
+            path.display(),
+            from,
+            CONFIG_VERSION,
+            e
+        ),
+    }
+
+    Ok(())
+}
+
+lazy_static! {
+    /// Every key a configuration file may contain, built from options with all optional values set
+    static ref KNOWN_KEYS: toml::value::Table = {
+        let rule = UsbRule {
+            vid: Some(String::new()),
+            pid: Some(String::new()),
+            product: Some(String::new()),
+            serial_number: Some(String::new()),
+        };
+
+        let image = super::ViewerButtonSkin {
+            pressed: Some(Default::default()),
+            released: Some(Default::default()),
+        };
+        let shape = super::ButtonShape {
+            size: Some(0.),
+            width: Some(0.),
+            height: Some(0.),
+            radius: Some(0.),
+            points: vec![[0., 0.]],
+            ..Default::default()
+        };
+        let mut options = ViewerOptions {
+            custom_tty: Some(String::new()),
+            profile: Some(String::new()),
+            theme: Some(String::new()),
+            skin: super::ViewerSkin {
+                background: Some(Default::default()),
+                default: image.clone(),
+                buttons: super::BUTTON_NAMES
+                    .iter()
+                    .map(|name| (name.to_string(), image.clone()))
+                    .collect(),
+            },
+            label_positions: super::BUTTON_NAMES
+                .iter()
+                .map(|name| (name.to_string(), Default::default()))
+                .collect(),
+            button_shape: shape.clone(),
+            button_shapes: super::BUTTON_NAMES
+                .iter()
+                .map(|name| (name.to_string(), shape.clone()))
+                .collect(),
+            animation: super::ViewerAnimations {
+                fade_out: 1,
+                ..Default::default()
+            },
+            case: super::ViewerCase {
+                points: vec![[0., 0.]],
+                color: Some(*super::DEFAULT_BACKGROUND_COLOR),
+                outline: Some(*super::DEFAULT_BACKGROUND_COLOR),
+            },
+            font: super::ViewerFont {
+                path: Some(Default::default()),
+                size: Some(0),
+                sizes: super::BUTTON_NAMES
+                    .iter()
+                    .map(|name| (name.to_string(), 0))
+                    .collect(),
+            },
+            ..Default::default()
+        };
+        options.detection.rules.push(rule.clone());
+        options.model_fingerprints.push(ModelFingerprint {
+            device: rule,
+            model: B0xxModel::R1,
+        });
+
+        match toml::Value::try_from(options) {
+            Ok(toml::Value::Table(keys)) => keys,
+            _ => unreachable!("options always serialize to a table"),
+        }
+    };
+}
+
+/// Lists the keys of `values` that aren't part of the configuration format, eg. typos
+pub fn unknown_keys(values: &toml::value::Table) -> Vec<String> {
+    let mut unknown = vec![];
+    let mut values = values.clone();
+
+    // Profiles are partial configurations, their keys are checked the same way
+    if let Some(toml::Value::Table(profiles)) = values.remove("profiles") {
+        for (name, profile) in profiles {
+            if let toml::Value::Table(profile) = profile {
+                let prefix = format!("profiles.{}", name);
+                collect_unknown_keys(&profile, &KNOWN_KEYS, &prefix, &mut unknown);
+            }
+        }
+    }
+
+    collect_unknown_keys(&values, &KNOWN_KEYS, "", &mut unknown);
+    unknown
+}
+
+fn collect_unknown_keys(
+    values: &toml::value::Table,
+    known: &toml::value::Table,
+    prefix: &str,
+    unknown: &mut Vec<String>,
+) {
+    for (key, value) in values {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+
+        match (known.get(key), value) {
+            (None, _) => unknown.push(path),
+            (Some(toml::Value::Table(known)), toml::Value::Table(values)) => {
+                collect_unknown_keys(values, known, &path, unknown);
+            }
+            (Some(toml::Value::Array(known)), toml::Value::Array(values)) => {
+                if let Some(toml::Value::Table(known)) = known.first() {
+                    for (i, value) in values.iter().enumerate() {
+                        if let toml::Value::Table(value) = value {
+                            collect_unknown_keys(value, known, &format!("{}[{}]", path, i), unknown);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Checks a configuration file without migrating it. Errors are what would prevent the file
+/// from loading, the returned warnings are problems that would be worked around
+pub fn validate_file(path: &std::path::Path) -> Result<Vec<String>, ViewerError> {
+    if !path.exists() {
+        return Err(ConfigError::NotFound.into());
+    }
+
+    let text = std::fs::read_to_string(path)?;
+    // Deserializing straight from the text keeps line and column information in errors
+    toml::from_str::<ViewerOptions>(&text).map_err(|e| ViewerError::from(ConfigError::from(e)))?;
+    let mut values: toml::value::Table =
+        toml::from_str(&text).map_err(|e| ViewerError::from(ConfigError::from(e)))?;
+
+    let mut warnings = vec![];
+    let version = file_version(&values)?;
+    if version < CONFIG_VERSION {
+        warnings.push(format!(
+            "version {} will be migrated to {} when loaded",
+            version, CONFIG_VERSION
+        ));
+        migrate(&mut values)?;
+    } else if version > CONFIG_VERSION {
+        warnings.push(format!(
+            "version {} was written by a newer b0xx_viewer, this build supports {}",
+            version, CONFIG_VERSION
+        ));
+    }
+
+    warnings.extend(
+        unknown_keys(&values)
+            .into_iter()
+            .map(|key| format!("unknown key `{}` will be ignored", key)),
+    );
+
+    let mut config = super::LayeredConfig {
+        file: Some((path.into(), super::ConfigLayer::from_file_table(path, values))),
+        ..Default::default()
+    };
+    let options = config.resolve()?.options;
+    warnings.extend(super::theme_warnings(&options));
+    warnings.extend(
+        options
+            .skin
+            .images()
+            .map(|image| options.asset_path(image))
+            .filter(|image| !image.is_file())
+            .map(|image| format!("skin image {} was not found", image.display())),
+    );
+    if !options.case.points.is_empty() && options.case.points.len() < 3 {
+        warnings.push("the case needs at least 3 points, it won't be drawn".into());
+    }
+    if let Some(font) = &options.font.path {
+        let font = options.asset_path(font);
+        if let Err(e) = conrod_core::text::font::from_file(&font) {
+            warnings.push(format!(
+                "font {} can't be used, labels will use the default font: {}",
+                font.display(),
+                e
+            ));
+        }
+    }
+
+    if let Some(name) = config.unknown_profile() {
+        warnings.push(format!(
+            "profile `{}` doesn't exist, the base configuration will be used",
+            name
+        ));
+    }
+
+    for profile in config.profile_names() {
+        config.select_profile(Some(&profile));
+        let resolved = config.resolve().map_err(|e| {
+            ViewerError::from(ConfigError::InvalidProfile(profile.clone(), e.to_string()))
+        })?;
+        warnings.extend(
+            super::theme_warnings(&resolved.options)
+                .into_iter()
+                .map(|warning| format!("profile `{}`: {}", profile, warning)),
+        );
+    }
+
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(text: &str) -> toml::value::Table {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn v1_files_are_migrated() {
+        let mut values = table(
+            "is_r2_b0xx = true\n\
+            background_color = { r = 1, g = 2, b = 3, a = 255 }\n\
+            [button_active_colors]\n\
+            a = { r = 255, g = 0, b = 0, a = 128 }\n\
+            b = \"#00FF00\"",
+        );
+        assert_eq!(migrate(&mut values).unwrap(), Some(1));
+        assert_eq!(values["version"].as_integer(), Some(CONFIG_VERSION as i64));
+        assert_eq!(values["model"].as_str(), Some("r2"));
+        assert!(!values.contains_key("is_r2_b0xx"));
+        assert_eq!(values["background_color"].as_str(), Some("#010203"));
+        assert_eq!(
+            values["button_active_colors"]["a"].as_str(),
+            Some("#FF000080")
+        );
+        assert_eq!(
+            values["button_active_colors"]["b"].as_str(),
+            Some("#00FF00")
+        );
+
+        // An explicit model wins, and r1 units don't get one
+        let mut values = table("is_r2_b0xx = true\nmodel = \"r1\"");
+        migrate(&mut values).unwrap();
+        assert_eq!(values["model"].as_str(), Some("r1"));
+        let mut values = table("is_r2_b0xx = false");
+        migrate(&mut values).unwrap();
+        assert!(!values.contains_key("model"));
+
+        let mut values = table("version = 2");
+        assert_eq!(migrate(&mut values).unwrap(), None);
+        let mut values = table("version = 99");
+        assert_eq!(migrate(&mut values).unwrap(), None);
+        assert_eq!(values["version"].as_integer(), Some(99));
+    }
+
+    #[test]
+    fn file_versions() {
+        assert_eq!(file_version(&table("")).unwrap(), 1);
+        assert_eq!(file_version(&table("version = 2")).unwrap(), 2);
+        assert!(file_version(&table("version = 0")).is_err());
+        assert!(file_version(&table("version = -1")).is_err());
+        assert!(file_version(&table("version = \"2\"")).is_err());
+    }
+
+    #[test]
+    fn unknown_keys_are_listed() {
+        let values = table(
+            "display_label = true\n\
+            [button_active_colors]\n\
+            a = \"red\"\n\
+            select = \"red\"\n\
+            [profiles.stream]\n\
+            chromeles = true\n\
+            [[detection.rules]]\n\
+            vid = \"0x2341\"\n\
+            vendor = \"arduino\"\n\
+            [[model_fingerprints]]\n\
+            pid = \"0x8036\"\n\
+            model = \"r2\"\n\
+            modle = \"r2\"",
+        );
+        assert_eq!(
+            unknown_keys(&values),
+            [
+                "profiles.stream.chromeles",
+                "button_active_colors.select",
+                "detection.rules[0].vendor",
+                "display_label",
+                "model_fingerprints[0].modle",
+            ]
+        );
+        assert!(unknown_keys(&table("version = 2\nlabels = { a = \"A\" }")).is_empty());
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("b0xx_viewer_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn migrated_files_are_backed_up() {
+        let dir = temp_dir("migration");
+        let path = dir.join(super::super::DEFAULT_FILENAME);
+        let original = "# my setup\nis_r2_b0xx = true\n";
+        std::fs::write(&path, original).unwrap();
+
+        let mut values = table(original);
+        migrate_file(&path, &mut values, FileMigration::Rewrite).unwrap();
+        let backup = dir.join(format!("{}.v1.bak", super::super::DEFAULT_FILENAME));
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), original);
+        let migrated = table(&std::fs::read_to_string(&path).unwrap());
+        assert_eq!(
+            migrated["version"].as_integer(),
+            Some(CONFIG_VERSION as i64)
+        );
+        assert_eq!(migrated["model"].as_str(), Some("r2"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn in_memory_migrations_leave_the_file_alone() {
+        let dir = temp_dir("inspection");
+        let path = dir.join(super::super::DEFAULT_FILENAME);
+        let original = "# my setup\nis_r2_b0xx = true\n";
+        std::fs::write(&path, original).unwrap();
+
+        let layer = super::super::ConfigLayer::from_file(&path, FileMigration::InMemory).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), original);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        let config = super::super::LayeredConfig {
+            file: Some((path, layer)),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.resolve().unwrap().options.model,
+            super::super::ViewerModelSelection::R2
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn validation() {
+        let dir = temp_dir("validation");
+        let path = dir.join(super::super::DEFAULT_FILENAME);
+        let validate = |text: &str| {
+            std::fs::write(&path, text).unwrap();
+            validate_file(&path).map_err(|e| e.to_string())
+        };
+
+        let error =
+            validate("display_labels = true\nbackground_color = \"#00EBFF\"\nscale = \"big\"\n")
+                .unwrap_err();
+        assert!(
+            error.contains("`scale`") && error.contains("line 3 column 9"),
+            "{}",
+            error
+        );
+        let error = validate("display_labels = true\n[button_active_colors\n").unwrap_err();
+        assert!(error.contains("line 2 column 22"), "{}", error);
+
+        assert_eq!(
+            validate("version = 2\ndisplay_labels = true\n").unwrap(),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            validate("is_r2_b0xx = true\ndisplay_lables = true\n").unwrap(),
+            [
+                "version 1 will be migrated to 2 when loaded",
+                "unknown key `display_lables` will be ignored",
+            ]
+        );
+        assert_eq!(
+            validate("version = 2\nprofile = \"strem\"\n[profiles.stream]\nscale = 2.0\n").unwrap(),
+            ["profile `strem` doesn't exist, the base configuration will be used"]
+        );
+        // Validating never touches the file
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(validate_file(&path).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
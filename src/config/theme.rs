@@ -0,0 +1,320 @@
+use super::{
+    ConfigError, ViewerButtonColors, ViewerButtonLabels, ViewerColor, ViewerModelSelection,
+    ViewerOptions,
+};
+use crate::error::ViewerError;
+use serde::{Deserialize, Serialize};
+
+/// Prefix of theme codes, the digit is the version of the encoding
+pub const THEME_CODE_PREFIX: &str = "b0xx-theme1:";
+const THEME_CODE_VERSION: u8 = 1;
+const BLANK: ViewerColor = ViewerColor(rgb::RGBA8::new(0, 0, 0, 0));
+
+/// Themes shipped with the viewer, selected with `--theme <name>` or `theme = "<name>"`.
+/// They sit right above the built-in defaults, so every other setting overrides them
+const BUNDLED_THEMES: &[(&str, &str)] = &[
+    ("b0xx", include_str!("../../cfg/themes/b0xx.toml")),
+    ("colorblind", include_str!("../../cfg/themes/colorblind.toml")),
+    ("gcc", include_str!("../../cfg/themes/gcc.toml")),
+    ("gcc-accessible", include_str!("../../cfg/themes/gcc-accessible.toml")),
+    ("high-contrast", include_str!("../../cfg/themes/high-contrast.toml")),
+    ("monochrome", include_str!("../../cfg/themes/monochrome.toml")),
+];
+
+pub fn bundled_theme_names() -> impl Iterator<Item = &'static str> {
+    BUNDLED_THEMES.iter().map(|(name, _)| *name)
+}
+
+/// Values of the bundled theme called `name`
+pub fn bundled_theme(name: &str) -> Option<toml::value::Table> {
+    let (_, text) = BUNDLED_THEMES.iter().find(|(theme, _)| *theme == name)?;
+    match toml::from_str(text) {
+        Ok(values) => Some(values),
+        Err(e) => {
+            error!("The bundled theme `{}` is invalid: {}", name, e);
+            None
+        }
+    }
+}
+
+/// The visual part of the configuration, as traded through theme codes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ViewerTheme {
+    pub display_labels: bool,
+    pub colored_rims: bool,
+    pub model: ViewerModelSelection,
+    pub background_color: ViewerColor,
+    pub button_inactive_colors: ViewerButtonColors,
+    pub button_active_colors: ViewerButtonColors,
+    pub labels: ViewerButtonLabels,
+}
+
+impl From<&ViewerOptions> for ViewerTheme {
+    fn from(options: &ViewerOptions) -> Self {
+        Self {
+            display_labels: options.display_labels,
+            colored_rims: options.colored_rims,
+            model: options.model,
+            background_color: options.background_color,
+            button_inactive_colors: options.button_inactive_colors,
+            button_active_colors: options.button_active_colors,
+            labels: options.labels.clone(),
+        }
+    }
+}
+
+fn invalid(reason: &str) -> ViewerError {
+    ConfigError::InvalidThemeCode(reason.into()).into()
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes
+        .iter()
+        .fold(0u8, |sum, byte| sum.rotate_left(1).wrapping_add(*byte))
+}
+
+impl ViewerTheme {
+    /// Encodes the theme as `b0xx-theme1:<base64>`. The payload is a version byte, a flags byte,
+    /// the background and button colors as RGBA, the labels that differ from the defaults as
+    /// `<button index> <length> <utf-8>` and a checksum byte
+    pub fn to_code(&self) -> String {
+        let model = match self.model {
+            ViewerModelSelection::Auto => 0,
+            ViewerModelSelection::R1 => 1,
+            ViewerModelSelection::R2 => 2,
+        };
+        let flags = self.display_labels as u8 | (self.colored_rims as u8) << 1 | model << 2;
+
+        let mut bytes = vec![THEME_CODE_VERSION, flags];
+        let colors = std::iter::once(self.background_color)
+            .chain(self.button_inactive_colors.to_array())
+            .chain(self.button_active_colors.to_array());
+        for color in colors {
+            bytes.extend_from_slice(&[color.r, color.g, color.b, color.a]);
+        }
+
+        let defaults = ViewerButtonLabels::default();
+        let labels = self.labels.to_array();
+        for (index, (label, default)) in labels.iter().zip(defaults.to_array()).enumerate() {
+            // Labels are cut to 255 bytes, on a character boundary
+            let mut len = label.len().min(255);
+            while !label.is_char_boundary(len) {
+                len -= 1;
+            }
+
+            if *label != default {
+                bytes.extend_from_slice(&[index as u8, len as u8]);
+                bytes.extend_from_slice(&label.as_bytes()[..len]);
+            }
+        }
+
+        bytes.push(checksum(&bytes));
+        format!("{}{}", THEME_CODE_PREFIX, base64url_encode(&bytes))
+    }
+
+    pub fn from_code(code: &str) -> Result<Self, ViewerError> {
+        let payload = code
+            .trim()
+            .strip_prefix(THEME_CODE_PREFIX)
+            .ok_or_else(|| invalid("it should start with b0xx-theme1:"))?;
+        let bytes =
+            base64url_decode(payload).ok_or_else(|| invalid("it contains invalid characters"))?;
+
+        let (checked, sum) = bytes
+            .split_last()
+            .map(|(sum, checked)| (checked, *sum))
+            .ok_or_else(|| invalid("it is empty"))?;
+        if checksum(checked) != sum {
+            return Err(invalid("it is incomplete or was altered"));
+        }
+
+        let mut reader = checked.iter().copied();
+        let mut next = |what: &str| {
+            reader
+                .next()
+                .ok_or_else(|| invalid(&format!("it is truncated before the {}", what)))
+        };
+
+        if next("version")? != THEME_CODE_VERSION {
+            return Err(invalid("it was made with an unsupported version"));
+        }
+
+        let flags = next("flags")?;
+        let model = match flags >> 2 & 0b11 {
+            0 => ViewerModelSelection::Auto,
+            1 => ViewerModelSelection::R1,
+            2 => ViewerModelSelection::R2,
+            _ => return Err(invalid("it selects an unknown model")),
+        };
+
+        let mut colors = [BLANK; 41];
+        for color in colors.iter_mut() {
+            let mut rgba = [0u8; 4];
+            for channel in rgba.iter_mut() {
+                *channel = next("colors")?;
+            }
+            *color = ViewerColor(rgb::RGBA8::new(rgba[0], rgba[1], rgba[2], rgba[3]));
+        }
+
+        let mut labels = ViewerButtonLabels::default().to_array().map(String::from);
+        while let Ok(index) = next("labels") {
+            let len = next("labels")? as usize;
+            let label = (0..len)
+                .map(|_| next("labels"))
+                .collect::<Result<Vec<u8>, _>>()?;
+
+            let slot = labels
+                .get_mut(index as usize)
+                .ok_or_else(|| invalid("it labels an unknown button"))?;
+            *slot =
+                String::from_utf8(label).map_err(|_| invalid("it contains an invalid label"))?;
+        }
+
+        let mut inactive = [BLANK; 20];
+        inactive.copy_from_slice(&colors[1..21]);
+        let mut active = [BLANK; 20];
+        active.copy_from_slice(&colors[21..41]);
+
+        Ok(Self {
+            display_labels: flags & 0b1 != 0,
+            colored_rims: flags & 0b10 != 0,
+            model,
+            background_color: colors[0],
+            button_inactive_colors: ViewerButtonColors::from_array(inactive),
+            button_active_colors: ViewerButtonColors::from_array(active),
+            labels: ViewerButtonLabels::from_array(labels),
+        })
+    }
+
+    /// Replaces the visual settings of a configuration file's values with this theme,
+    /// the rest of the file is left untouched
+    pub fn apply_to(&self, values: &mut toml::value::Table) -> Result<(), ViewerError> {
+        let theme = match toml::Value::try_from(self) {
+            Ok(toml::Value::Table(theme)) => theme,
+            Ok(_) => unreachable!("themes always serialize to a table"),
+            Err(e) => return Err(ConfigError::SerializationError(e).into()),
+        };
+
+        values.extend(theme);
+        Ok(())
+    }
+}
+
+const BASE64URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Unpadded base64url, which survives being pasted in chat and URLs
+fn base64url_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len() * 4 / 3 + 3);
+    for chunk in bytes.chunks(3) {
+        let buf = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (buf[0] as u32) << 16 | (buf[1] as u32) << 8 | buf[2] as u32;
+        for i in 0..=chunk.len() {
+            encoded.push(BASE64URL[(n >> (18 - 6 * i) & 63) as usize] as char);
+        }
+    }
+    encoded
+}
+
+fn base64url_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(encoded.len() * 3 / 4);
+    let mut buf = 0u32;
+    let mut bits = 0;
+    for c in encoded.bytes() {
+        let value = BASE64URL.iter().position(|b| *b == c)? as u32;
+        buf = buf << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buf >> bits & 0xff) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_round_trip(theme: &ViewerTheme) {
+        let code = theme.to_code();
+        assert!(code.starts_with(THEME_CODE_PREFIX));
+        assert_eq!(&ViewerTheme::from_code(&code).unwrap(), theme);
+    }
+
+    fn payload(theme: &ViewerTheme) -> Vec<u8> {
+        let code = theme.to_code();
+        base64url_decode(&code[THEME_CODE_PREFIX.len()..]).unwrap()
+    }
+
+    fn encode(bytes: &[u8]) -> String {
+        format!("{}{}", THEME_CODE_PREFIX, base64url_encode(bytes))
+    }
+
+    #[test]
+    fn default_and_bundled_themes_round_trip() {
+        assert_round_trip(&ViewerTheme::from(&ViewerOptions::default()));
+
+        for name in bundled_theme_names() {
+            let options: ViewerOptions = toml::Value::Table(bundled_theme(name).unwrap())
+                .try_into()
+                .unwrap();
+            assert_round_trip(&ViewerTheme::from(&options));
+        }
+    }
+
+    #[test]
+    fn non_ascii_labels_round_trip() {
+        let mut theme = ViewerTheme::from(&ViewerOptions::default());
+        theme.labels.a = "Ä→🎮".into();
+        theme.labels.mod_x = "é".repeat(100);
+        assert_round_trip(&theme);
+
+        // Long labels are cut without splitting a character
+        theme.labels.mod_x = "é".repeat(200);
+        let decoded = ViewerTheme::from_code(&theme.to_code()).unwrap();
+        assert_eq!(decoded.labels.mod_x, "é".repeat(127));
+        assert_eq!(decoded.labels.a, "Ä→🎮");
+    }
+
+    #[test]
+    fn altered_codes_are_rejected() {
+        let theme = ViewerTheme::from(&ViewerOptions::default());
+        let mut bytes = payload(&theme);
+
+        let last = bytes.len() - 1;
+        bytes[last] = bytes[last].wrapping_add(1);
+        assert!(ViewerTheme::from_code(&encode(&bytes)).is_err());
+
+        bytes[last] = bytes[last].wrapping_sub(1);
+        bytes[5] ^= 0xff;
+        assert!(ViewerTheme::from_code(&encode(&bytes)).is_err());
+    }
+
+    #[test]
+    fn truncated_codes_are_rejected() {
+        let theme = ViewerTheme::from(&ViewerOptions::default());
+        let code = theme.to_code();
+        assert!(ViewerTheme::from_code(&code[..code.len() - 8]).is_err());
+        assert!(ViewerTheme::from_code(THEME_CODE_PREFIX).is_err());
+
+        // A valid checksum over a payload cut in the middle of the colors
+        let mut bytes = payload(&theme);
+        bytes.truncate(30);
+        bytes.push(checksum(&bytes));
+        let error = ViewerTheme::from_code(&encode(&bytes)).unwrap_err();
+        assert!(error.to_string().contains("truncated"), "{}", error);
+    }
+
+    #[test]
+    fn wrong_prefixes_are_rejected() {
+        let code = ViewerTheme::from(&ViewerOptions::default()).to_code();
+        let payload = &code[THEME_CODE_PREFIX.len()..];
+        assert!(ViewerTheme::from_code(&format!("b0xx-theme2:{}", payload)).is_err());
+        assert!(ViewerTheme::from_code(payload).is_err());
+        assert!(ViewerTheme::from_code(&format!("  {}\n", code)).is_ok());
+    }
+}
@@ -0,0 +1,106 @@
+use crate::error::ViewerError;
+
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+type FileStamp = Option<(std::time::SystemTime, u64)>;
+
+fn stamp(path: &std::path::Path) -> FileStamp {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Polls `path` and notifies the returned channel whenever the file is saved.
+/// Editors often write files in several steps, so a change is only reported once the file
+/// stayed the same for a whole poll interval. The watcher stops when the receiver is dropped
+pub fn watch_file(
+    path: std::path::PathBuf,
+) -> Result<crossbeam_channel::Receiver<()>, ViewerError> {
+    poll_file(path, POLL_INTERVAL)
+}
+
+fn poll_file(
+    path: std::path::PathBuf,
+    interval: std::time::Duration,
+) -> Result<crossbeam_channel::Receiver<()>, ViewerError> {
+    let (tx, rx) = crossbeam_channel::bounded(1);
+    let mut last_seen = stamp(&path);
+
+    std::thread::Builder::new()
+        .name("b0xx_viewer_config_watch".into())
+        .spawn(move || {
+            let mut pending: FileStamp = None;
+            loop {
+                std::thread::sleep(interval);
+
+                let current = stamp(&path);
+                if current == last_seen || current.is_none() {
+                    pending = None;
+                    continue;
+                }
+
+                if pending != current {
+                    pending = current;
+                    continue;
+                }
+
+                debug!("{} changed on disk", path.display());
+                last_seen = current;
+                pending = None;
+
+                match tx.try_send(()) {
+                    Ok(()) | Err(crossbeam_channel::TrySendError::Full(_)) => {}
+                    Err(crossbeam_channel::TrySendError::Disconnected(_)) => return,
+                }
+            }
+        })?;
+
+    Ok(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const INTERVAL: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn saved_files_are_reported() {
+        let dir = std::env::temp_dir().join(format!("b0xx_viewer_watch_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("b0xx_viewer_config.toml");
+        std::fs::write(&path, "scale = 1.0\n").unwrap();
+        let rx = poll_file(path.clone(), INTERVAL).unwrap();
+        let settle = || std::thread::sleep(INTERVAL * 4);
+
+        settle();
+        assert!(rx.try_recv().is_err());
+        std::fs::write(&path, "scale = 2.0\n# saved\n").unwrap();
+        assert!(rx.recv_timeout(INTERVAL * 10).is_ok());
+
+        // Nothing is reported while the file keeps changing, only once it settled
+        for i in 0..60 {
+            std::fs::write(&path, format!("scale = 1.0\n#{}\n", "-".repeat(i))).unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(rx.try_recv().is_err());
+        assert!(rx.recv_timeout(INTERVAL * 10).is_ok());
+
+        // Saves the viewer didn't pick up yet are reported once
+        for content in ["scale = 3.0\n", "scale = 3.5\n\n"] {
+            std::fs::write(&path, content).unwrap();
+            settle();
+        }
+        assert_eq!(rx.try_iter().count(), 1);
+
+        // Editors replacing the file delete it first
+        std::fs::remove_file(&path).unwrap();
+        settle();
+        assert!(rx.try_recv().is_err());
+        std::fs::write(&path, "scale = 4.0\n# replaced\n").unwrap();
+        assert!(rx.recv_timeout(INTERVAL * 10).is_ok());
+
+        drop(rx);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
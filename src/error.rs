@@ -6,12 +6,24 @@ pub enum ViewerError {
     IoError(#[from] std::io::Error),
     #[error(transparent)]
     SerialPortError(#[from] serialport::Error),
+    #[error("Permission denied while opening {port}.\n{guidance}")]
+    SerialPermissionDenied { port: String, guidance: String },
+    #[error("{0} is already in use by another program. Is another viewer or a firmware flasher running?")]
+    PortBusy(String),
+    #[error("Another viewer instance is already running for {0}")]
+    AlreadyRunning(String),
+    #[error("{0} is not a USB device")]
+    NotAUsbDevice(String),
     #[error("Internal serial thread error: {0}")]
     SerialThreadError(#[from] crossbeam_channel::RecvError),
     #[error("Configuration error: {0}")]
     ConfigError(#[from] crate::config::ConfigError),
     #[error("The state report transmitted over serial was malformed")]
     MalformedSerialReport,
+    #[error("ImageError: {0}")]
+    ImageError(#[from] image::ImageError),
+    #[error("The raw capture file is malformed (line {0})")]
+    MalformedCapture(usize),
     #[error(transparent)]
     Other(#[from] anyhow::Error),
     #[error("An unknown error occured, sorry")]
@@ -0,0 +1,64 @@
+use crate::error::ViewerError;
+
+/// Held for the lifetime of the viewer to keep other instances away from the same device.
+/// The OS releases the lock when the process exits, so stale lock files are harmless
+#[derive(Debug)]
+#[cfg_attr(feature = "fake_serial", allow(dead_code))]
+pub struct InstanceLock {
+    _file: std::fs::File,
+}
+
+#[cfg_attr(feature = "fake_serial", allow(dead_code))]
+impl InstanceLock {
+    fn lock_path(port_name: &str) -> std::path::PathBuf {
+        let sanitized: String = port_name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+
+        std::env::temp_dir().join(format!("b0xx_viewer-{}.lock", sanitized))
+    }
+
+    pub fn acquire(port_name: &str) -> Result<Self, ViewerError> {
+        let path = Self::lock_path(port_name);
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(std::fs::TryLockError::WouldBlock) => {
+                return Err(ViewerError::AlreadyRunning(port_name.into()));
+            }
+            Err(std::fs::TryLockError::Error(e)) => return Err(e.into()),
+        }
+
+        use std::io::Write as _;
+        let mut writer = &file;
+        writer.set_len(0)?;
+        writeln!(writer, "{}", std::process::id())?;
+
+        debug!("Acquired instance lock {:?}", path);
+        Ok(Self { _file: file })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_instance_per_port() {
+        let port = format!("/dev/ttyTEST{}", std::process::id());
+        let lock = InstanceLock::acquire(&port).unwrap();
+        assert!(matches!(
+            InstanceLock::acquire(&port),
+            Err(ViewerError::AlreadyRunning(name)) if name == port
+        ));
+        drop(lock);
+        InstanceLock::acquire(&port).unwrap();
+        let _ = std::fs::remove_file(InstanceLock::lock_path(&port));
+    }
+}
@@ -18,7 +18,7 @@ impl Logger {
         }
 
         fern::Dispatch::new()
-            .chain(stdout())
+            .chain(stderr())
             .chain(filelog())
             .apply()
             .unwrap();
@@ -27,7 +27,8 @@ impl Logger {
     }
 }
 
-fn stdout() -> fern::Dispatch {
+/// Logs go to stderr so that commands printing to stdout, eg. `--print-udev-rule`, can be piped
+fn stderr() -> fern::Dispatch {
     let colors = ColoredLevelConfig::new()
         .error(Color::Red)
         .warn(Color::Yellow)
@@ -46,7 +47,7 @@ fn stdout() -> fern::Dispatch {
             ))
         })
         .level(log::LevelFilter::Info)
-        .chain(std::io::stdout())
+        .chain(std::io::stderr())
 }
 
 fn filelog() -> fern::Dispatch {
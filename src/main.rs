@@ -4,13 +4,15 @@
 extern crate log;
 
 mod b0xx_state;
+mod capture;
 mod cli;
-#[macro_use]
 mod config;
 mod error;
+mod instance;
 mod serial_probe;
 mod ui;
 mod logger;
+mod permissions;
 
 pub use self::error::*;
 
@@ -24,9 +26,62 @@ pub fn main() {
     let mut logger = logger::Logger::new();
     logger.init();
 
-    let options = cli::cli_options();
+    let cli = match cli::parse_args(std::env::args_os()) {
+        Ok(cli) => cli,
+        Err(cli::CliError::Clap(e)) => e.exit(),
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    match &cli.command {
+        cli::CliCommand::InitConfig => std::process::exit(cli::init_config()),
+        cli::CliCommand::Config(command) => {
+            std::process::exit(cli::run_config_command(&cli, command))
+        }
+        cli::CliCommand::Theme(command) => {
+            std::process::exit(cli::run_theme_command(&cli, command))
+        }
+        cli::CliCommand::PrintUdevRule | cli::CliCommand::Run => {}
+    }
+
+    let mut config = cli::load_config(&cli, config::FileMigration::Rewrite);
+    let options = cli::resolve_config(&mut config);
+    let mut probe_options = options.probe_options();
+
+    if cli.command == cli::CliCommand::PrintUdevRule {
+        match serial_probe::find_b0xx_port(&probe_options.detection)
+            .and_then(|port| permissions::udev_rule(&port))
+        {
+            Ok(rule) => println!("{}", rule),
+            Err(e) => {
+                error!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(capture_path) = cli.capture_raw {
+        match capture::RawCapture::create(&capture_path) {
+            Ok(raw_capture) => {
+                info!("Capturing raw serial traffic to {:?}", capture_path);
+                probe_options.capture = Some(raw_capture.into_shared());
+            }
+            Err(e) => {
+                error!("{}", e);
+                return;
+            }
+        }
+    }
+
+    let rx = match cli.replay_raw {
+        Some(replay_path) => capture::start_replay(&replay_path),
+        None => serial_probe::start_serial_probe(&probe_options),
+    };
 
-    let rx = match serial_probe::start_serial_probe(&options.custom_tty) {
+    let rx = match rx {
         Ok(rx) => rx,
         Err(e) => {
             error!("{}", e);
@@ -35,5 +90,5 @@ pub fn main() {
     };
 
     info!("Serial probe up and running");
-    ui::start_gui(rx, options)
+    ui::start_gui(rx, probe_options, config, options)
 }
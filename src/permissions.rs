@@ -0,0 +1,224 @@
+use crate::error::ViewerError;
+
+/// Explains why the current user can't open `port` and how to fix it
+#[cfg(target_os = "linux")]
+#[cfg_attr(feature = "fake_serial", allow(dead_code))]
+pub fn diagnose(port: &str) -> String {
+    use std::os::unix::fs::{MetadataExt as _, PermissionsExt as _};
+
+    let metadata = match std::fs::metadata(port) {
+        Ok(metadata) => metadata,
+        Err(e) => return format!("Could not inspect {}: {}", port, e),
+    };
+
+    let mode = metadata.permissions().mode();
+    let group_file = std::fs::read_to_string("/etc/group").unwrap_or_default();
+    let group =
+        group_name(&group_file, metadata.gid()).unwrap_or_else(|| metadata.gid().to_string());
+    let user = current_user_name().unwrap_or_else(|| "$USER".into());
+    let session_groups = session_gids();
+    let in_session = session_groups.contains(&metadata.gid());
+    let in_group_file = group_members(&group_file, metadata.gid()).contains(&user);
+
+    debug!(
+        "{}: uid={} gid={} ({}) mode={:o}, session groups: {:?}",
+        port,
+        metadata.uid(),
+        metadata.gid(),
+        group,
+        mode & 0o777,
+        session_groups
+    );
+
+    let udev_hint = "Alternatively, install a udev rule granting access to the logged-in user:\n    \
+        b0xx_viewer --print-udev-rule > 50-b0xx.rules && sudo install -m 644 50-b0xx.rules /etc/udev/rules.d/\n    \
+        sudo udevadm control --reload-rules && sudo udevadm trigger";
+
+    if mode & 0o060 != 0o060 {
+        return format!(
+            "{} is owned by group `{}` but the group has no read/write access (mode {:o}).\n{}",
+            port,
+            group,
+            mode & 0o777,
+            udev_hint
+        );
+    }
+
+    if in_session {
+        return format!(
+            "Your session is already in group `{}`, which owns {}. \
+            Another program may be restricting access to the port, or an ACL is denying it.\n{}",
+            group, port, udev_hint
+        );
+    }
+
+    if in_group_file {
+        return format!(
+            "User `{}` was added to group `{}`, but the current session predates it.\n\
+            Log out and back in (or reboot), or run the viewer from `newgrp {}`.",
+            user, group, group
+        );
+    }
+
+    format!(
+        "User `{}` is not a member of group `{}`, which owns {}.\nRun:\n    \
+        sudo usermod -aG {} {}\nthen log out and back in.\n{}",
+        user, group, port, group, user, udev_hint
+    )
+}
+
+#[cfg(not(target_os = "linux"))]
+#[cfg_attr(feature = "fake_serial", allow(dead_code))]
+pub fn diagnose(port: &str) -> String {
+    format!(
+        "Make sure your user is allowed to access {} and that no other program is using it.",
+        port
+    )
+}
+
+#[cfg(target_os = "linux")]
+fn proc_status_field(field: &str) -> Option<String> {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix(field).map(|v| v.trim().to_string()))
+}
+
+#[cfg(target_os = "linux")]
+fn session_gids() -> Vec<u32> {
+    let mut gids: Vec<u32> = proc_status_field("Groups:")
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter_map(|gid| gid.parse().ok())
+        .collect();
+
+    // The effective gid isn't always listed among the supplementary groups
+    if let Some(egid) = proc_status_field("Gid:")
+        .and_then(|ids| ids.split_whitespace().nth(1).and_then(|gid| gid.parse().ok()))
+    {
+        gids.push(egid);
+    }
+
+    gids
+}
+
+#[cfg(target_os = "linux")]
+fn current_user_name() -> Option<String> {
+    let uid: u32 = proc_status_field("Uid:")?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()?;
+
+    std::fs::read_to_string("/etc/passwd")
+        .ok()?
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() > 2 && fields[2].parse() == Ok(uid))
+        .map(|fields| fields[0].to_string())
+        .or_else(|| std::env::var("USER").ok())
+}
+
+/// Finds the entry of `gid` in the contents of `/etc/group`
+#[cfg(target_os = "linux")]
+fn group_entry(group_file: &str, gid: u32) -> Option<Vec<&str>> {
+    group_file
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() > 3 && fields[2].parse() == Ok(gid))
+}
+
+#[cfg(target_os = "linux")]
+fn group_name(group_file: &str, gid: u32) -> Option<String> {
+    group_entry(group_file, gid).map(|fields| fields[0].to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn group_members(group_file: &str, gid: u32) -> Vec<String> {
+    group_entry(group_file, gid)
+        .map(|fields| {
+            fields[3]
+                .split(',')
+                .filter(|member| !member.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Builds a udev rule giving the logged-in user access to the given USB serial device
+pub fn udev_rule(port: &serialport::SerialPortInfo) -> Result<String, ViewerError> {
+    let portinfo = match &port.port_type {
+        serialport::SerialPortType::UsbPort(portinfo) => portinfo,
+        _ => return Err(ViewerError::NotAUsbDevice(port.port_name.clone())),
+    };
+
+    Ok(format!(
+        "# b0xx_viewer: {} ({})\n\
+        SUBSYSTEM==\"tty\", ATTRS{{idVendor}}==\"{:04x}\", ATTRS{{idProduct}}==\"{:04x}\", MODE=\"0660\", TAG+=\"uaccess\"",
+        portinfo.product.as_deref().unwrap_or("unknown product"),
+        port.port_name,
+        portinfo.vid,
+        portinfo.pid,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn udev_rules() {
+        let port = serialport::SerialPortInfo {
+            port_name: "/dev/ttyACM0".into(),
+            port_type: serialport::SerialPortType::UsbPort(serialport::UsbPortInfo {
+                vid: 0x2341,
+                pid: 0x8036,
+                serial_number: None,
+                manufacturer: None,
+                product: Some("Arduino Leonardo".into()),
+            }),
+        };
+        let rule = udev_rule(&port).unwrap();
+        let lines: Vec<&str> = rule.lines().collect();
+        assert_eq!(lines[0], "# b0xx_viewer: Arduino Leonardo (/dev/ttyACM0)");
+        assert_eq!(
+            lines[1],
+            "SUBSYSTEM==\"tty\", ATTRS{idVendor}==\"2341\", ATTRS{idProduct}==\"8036\", MODE=\"0660\", TAG+=\"uaccess\""
+        );
+        assert_eq!(lines.len(), 2);
+
+        let port = serialport::SerialPortInfo {
+            port_name: "/dev/ttyS0".into(),
+            port_type: serialport::SerialPortType::PciPort,
+        };
+        assert!(matches!(
+            udev_rule(&port),
+            Err(ViewerError::NotAUsbDevice(name)) if name == "/dev/ttyS0"
+        ));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn group_membership() {
+        let group_file = "root:x:0:\n\
+            # not a group\n\
+            uucp:x:14:alice\n\
+            dialout:x:20:alice,bob,\n\
+            broken:x:21\n";
+        assert_eq!(group_name(group_file, 20).as_deref(), Some("dialout"));
+        assert_eq!(group_members(group_file, 20), ["alice", "bob"]);
+        assert_eq!(group_members(group_file, 14), ["alice"]);
+        assert!(group_members(group_file, 0).is_empty());
+        assert_eq!(group_name(group_file, 21), None);
+        assert_eq!(group_name(group_file, 99), None);
+        assert!(group_members("", 20).is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn missing_ports_are_diagnosed() {
+        let guidance = diagnose("/dev/b0xx_viewer_missing_port");
+        assert!(guidance.starts_with("Could not inspect /dev/b0xx_viewer_missing_port"));
+    }
+}
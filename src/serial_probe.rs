@@ -1,136 +1,395 @@
 use crate::b0xx_state::*;
+use crate::capture::SharedCapture;
 use crate::error::ViewerError;
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 struct WhitelistFile {
-    arduino: Vec<UsbStringDef>,
+    arduino: Vec<UsbRule>,
 }
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-struct UsbStringDef {
-    pub vid: String,
-    pub pid: String,
+
+/// A USB device detection rule as written in configuration files.
+/// Every criterion that is set must match; `product` and `serial_number` accept `*` and `?` wildcards
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsbRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub product: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial_number: Option<String>,
 }
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
-struct UsbDefinition {
-    pub vid: u16,
-    pub pid: u16,
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct UsbMatcher {
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub product: Option<String>,
+    pub serial_number: Option<String>,
+}
+
+fn parse_usb_id(id: &str) -> Result<u16, std::num::ParseIntError> {
+    u16::from_str_radix(id.trim_start_matches("0x").trim_start_matches("0X"), 16)
 }
 
-impl std::convert::TryFrom<UsbStringDef> for UsbDefinition {
+impl std::convert::TryFrom<UsbRule> for UsbMatcher {
     type Error = std::num::ParseIntError;
 
-    fn try_from(def: UsbStringDef) -> Result<Self, Self::Error> {
+    fn try_from(rule: UsbRule) -> Result<Self, Self::Error> {
         Ok(Self {
-            pid: u16::from_str_radix(def.pid.trim_start_matches("0x"), 16)?,
-            vid: u16::from_str_radix(def.vid.trim_start_matches("0x"), 16)?,
+            vid: rule.vid.as_deref().map(parse_usb_id).transpose()?,
+            pid: rule.pid.as_deref().map(parse_usb_id).transpose()?,
+            product: rule.product,
+            serial_number: rule.serial_number,
         })
     }
 }
 
+impl UsbMatcher {
+    fn is_empty(&self) -> bool {
+        self.vid.is_none()
+            && self.pid.is_none()
+            && self.product.is_none()
+            && self.serial_number.is_none()
+    }
+
+    fn matches(&self, portinfo: &serialport::UsbPortInfo) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+
+        fn matches_pattern(pattern: &Option<String>, value: &Option<String>) -> bool {
+            match (pattern, value) {
+                (Some(pattern), Some(value)) => wildcard_match(pattern, value),
+                (Some(_), None) => false,
+                (None, _) => true,
+            }
+        }
+
+        self.vid.is_none_or(|vid| vid == portinfo.vid)
+            && self.pid.is_none_or(|pid| pid == portinfo.pid)
+            && matches_pattern(&self.product, &portinfo.product)
+            && matches_pattern(&self.serial_number, &portinfo.serial_number)
+    }
+}
+
+/// Minimal glob matching supporting `*` (any run of characters) and `?` (any single character)
+fn wildcard_match(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while v < value.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == value[v]) {
+            p += 1;
+            v += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, v));
+            p += 1;
+        } else if let Some((star_p, star_v)) = backtrack {
+            p = star_p + 1;
+            v = star_v + 1;
+            backtrack = Some((star_p, star_v + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn compile_rule(kind: &str, rule: &UsbRule) -> Option<UsbMatcher> {
+    use std::convert::TryFrom as _;
+    match UsbMatcher::try_from(rule.clone()) {
+        Ok(matcher) if matcher.is_empty() => {
+            warn!("Ignoring empty {} rule", kind);
+            None
+        }
+        Ok(matcher) => Some(matcher),
+        Err(e) => {
+            warn!("Ignoring invalid {} rule {:?}: {}", kind, rule, e);
+            None
+        }
+    }
+}
+
+/// Maps a USB descriptor to a B0XX model, for units that can't be told apart by their reports alone
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelFingerprint {
+    #[serde(flatten)]
+    pub device: UsbRule,
+    pub model: B0xxModel,
+}
+
 const ARDUINO_WHITELIST_BYTES: &[u8] = include_bytes!("../assets/arduino_whitelist.toml");
 lazy_static! {
-    static ref ARDUINO_WHITELIST: Vec<UsbDefinition> = {
+    static ref ARDUINO_WHITELIST: Vec<UsbMatcher> = {
         let res: WhitelistFile = toml::from_slice(ARDUINO_WHITELIST_BYTES).unwrap();
         use std::convert::TryFrom as _;
         res.arduino
             .into_iter()
-            .map(|s_def| UsbDefinition::try_from(s_def).unwrap())
+            .map(|rule| UsbMatcher::try_from(rule).unwrap())
             .collect()
     };
+    static ref B0XX_RULES: Vec<UsbMatcher> = vec![
+        UsbMatcher {
+            vid: Some(0x2341),
+            pid: Some(0x8036),
+            product: None,
+            serial_number: None,
+        },
+        UsbMatcher {
+            vid: None,
+            pid: None,
+            product: Some("Arduino_Leonardo".into()),
+            serial_number: None,
+        },
+    ];
+}
+
+/// Decides which serial port gets picked up by the probe.
+///
+/// A custom TTY bypasses every rule. Otherwise a USB port is accepted if it matches
+/// the built-in B0XX rules, the user-supplied rules, or - when relaxed - any Arduino-compatible board
+#[derive(Debug, Clone)]
+pub struct DetectionPolicy {
+    pub custom_tty: Option<String>,
+    pub relax_arduino: bool,
+    rules: Vec<UsbMatcher>,
+    fingerprints: Vec<(UsbMatcher, B0xxModel)>,
+}
+
+impl DetectionPolicy {
+    pub fn new(
+        custom_tty: Option<String>,
+        relax_arduino: bool,
+        rules: &[UsbRule],
+        fingerprints: &[ModelFingerprint],
+    ) -> Self {
+        Self {
+            custom_tty,
+            relax_arduino,
+            rules: rules
+                .iter()
+                .filter_map(|rule| compile_rule("detection", rule))
+                .collect(),
+            fingerprints: fingerprints
+                .iter()
+                .filter_map(|f| compile_rule("model fingerprint", &f.device).map(|m| (m, f.model)))
+                .collect(),
+        }
+    }
+
+    #[cfg_attr(feature = "fake_serial", allow(dead_code))]
+    fn matches(&self, port: &serialport::SerialPortInfo) -> bool {
+        if let Some(custom_tty) = &self.custom_tty {
+            return port.port_name == *custom_tty;
+        }
+
+        let portinfo = match &port.port_type {
+            serialport::SerialPortType::UsbPort(portinfo) => portinfo,
+            _ => return false,
+        };
+
+        B0XX_RULES
+            .iter()
+            .chain(self.rules.iter())
+            .any(|rule| rule.matches(portinfo))
+            || (self.relax_arduino && ARDUINO_WHITELIST.iter().any(|rule| rule.matches(portinfo)))
+    }
+
+    #[cfg_attr(feature = "fake_serial", allow(dead_code))]
+    fn fingerprint(&self, port: &serialport::SerialPortInfo) -> Option<B0xxModel> {
+        match &port.port_type {
+            serialport::SerialPortType::UsbPort(portinfo) => self
+                .fingerprints
+                .iter()
+                .find(|(matcher, _)| matcher.matches(portinfo))
+                .map(|(_, model)| *model),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SerialFlowControl {
+    None,
+    Software,
+    Hardware,
+}
+
+impl From<SerialFlowControl> for serialport::FlowControl {
+    fn from(value: SerialFlowControl) -> Self {
+        match value {
+            SerialFlowControl::None => serialport::FlowControl::None,
+            SerialFlowControl::Software => serialport::FlowControl::Software,
+            SerialFlowControl::Hardware => serialport::FlowControl::Hardware,
+        }
+    }
+}
+
+/// How reports are requested from the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SerialHandshake {
+    /// Raise RTS before every read and lower it afterwards; this is what the stock B0XX firmware expects
+    Rts,
+    /// The device sends reports on its own, RTS is left untouched
+    Streaming,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SerialLinkOptions {
+    pub baud_rate: u32,
+    pub flow_control: SerialFlowControl,
+    pub timeout_ms: u64,
+    pub handshake: SerialHandshake,
+}
+
+impl Default for SerialLinkOptions {
+    fn default() -> Self {
+        Self {
+            baud_rate: 115_200,
+            flow_control: SerialFlowControl::Hardware,
+            timeout_ms: 500,
+            handshake: SerialHandshake::Rts,
+        }
+    }
+}
+
+#[cfg_attr(feature = "fake_serial", allow(dead_code))]
+#[derive(Debug, Clone)]
+pub struct ProbeOptions {
+    pub detection: DetectionPolicy,
+    pub link: SerialLinkOptions,
+    pub capture: Option<SharedCapture>,
+    /// Holds an instance lock on the port while it's open, see `InstanceLock`
+    pub single_instance: bool,
 }
 
 #[cfg_attr(feature = "fake_serial", allow(dead_code))]
 #[derive(Debug)]
 pub enum B0xxMessage {
     State(B0xxState),
+    Model(B0xxModel),
     Error(ViewerError),
     Reconnect,
     Quit,
 }
 
 #[inline]
-pub fn reconnect(custom_tty: &Option<String>) -> crossbeam_channel::Receiver<B0xxMessage> {
-    use backoff::backoff::Backoff as _;
-    let mut backoff = backoff::ExponentialBackoff::default();
-    loop {
-        if let Ok(new_rx) = start_serial_probe(custom_tty) {
-            return new_rx;
-        }
+/// Connects to the device again in the background, with an exponential backoff between attempts.
+/// The returned channel delivers the new probe's messages once it's up
+pub fn reconnect(
+    options: ProbeOptions,
+) -> crossbeam_channel::Receiver<crossbeam_channel::Receiver<B0xxMessage>> {
+    let (tx, rx) = crossbeam_channel::bounded(1);
+    std::thread::spawn(move || {
+        use backoff::backoff::Backoff as _;
+        let mut backoff = backoff::ExponentialBackoff::default();
+        loop {
+            match start_serial_probe(&options) {
+                Ok(new_rx) => {
+                    let _ = tx.send(new_rx);
+                    return;
+                }
+                Err(e) => debug!("Reconnection attempt failed: {}", e),
+            }
 
-        if let Some(backoff_duration) = backoff.next_backoff() {
-            std::thread::sleep(backoff_duration);
+            if let Some(backoff_duration) = backoff.next_backoff() {
+                std::thread::sleep(backoff_duration);
+            }
         }
-    }
+    });
+
+    rx
+}
+
+/// Reports a streaming device can send ahead of the UI, about 60ms worth at 1kHz
+#[cfg(not(feature = "fake_serial"))]
+const STREAMING_QUEUE_LEN: usize = 64;
+
+#[cfg_attr(feature = "fake_serial", allow(dead_code))]
+pub fn find_b0xx_port(
+    detection: &DetectionPolicy,
+) -> Result<serialport::SerialPortInfo, ViewerError> {
+    serialport::available_ports()?
+        .into_iter()
+        .find(|port| detection.matches(port))
+        .ok_or(ViewerError::B0xxNotFound)
 }
 
 #[cfg(not(feature = "fake_serial"))]
 pub fn start_serial_probe(
-    custom_tty: &Option<String>,
+    options: &ProbeOptions,
 ) -> Result<crossbeam_channel::Receiver<B0xxMessage>, ViewerError> {
-    let b0xx_port = serialport::available_ports()?
-        .into_iter()
-        .find(move |port| {
-            if let Some(custom_tty) = custom_tty {
-                if port.port_name == *custom_tty {
-                    return true;
-                }
-            } else if let serialport::SerialPortType::UsbPort(portinfo) = &port.port_type {
-                if std::env::var("RELAX_ARDUINO_DETECT").is_ok() {
-                    if ARDUINO_WHITELIST
-                        .iter()
-                        .any(|def| def.vid == portinfo.vid && def.pid == portinfo.pid)
-                    {
-                        return true;
-                    }
-                } else if portinfo.vid == 9025 && portinfo.pid == 32822 {
-                    return true;
-                }
+    let b0xx_port = find_b0xx_port(&options.detection)?;
 
-                if let Some(product) = &portinfo.product {
-                    if product == "Arduino_Leonardo" {
-                        return true;
-                    }
-                }
-            }
+    info!("Found B0XX on port {}", b0xx_port.port_name);
 
-            false
-        })
-        .ok_or_else(|| ViewerError::B0xxNotFound)?;
+    let fingerprinted_model = options.detection.fingerprint(&b0xx_port);
+    let link = options.link;
+    let capture = options.capture.clone();
+    let rts_handshake = link.handshake == SerialHandshake::Rts;
+
+    let port_builder = serialport::new(&b0xx_port.port_name, link.baud_rate)
+        .data_bits(serialport::DataBits::Eight)
+        .flow_control(link.flow_control.into())
+        .parity(serialport::Parity::None)
+        .stop_bits(serialport::StopBits::One)
+        .timeout(std::time::Duration::from_millis(link.timeout_ms));
+
+    // Locked for as long as the port is open, including after reconnecting to another port
+    let instance_lock = if options.single_instance {
+        Some(crate::instance::InstanceLock::acquire(&b0xx_port.port_name)?)
+    } else {
+        None
+    };
 
-    info!("Found B0XX on port {}", b0xx_port.port_name);
+    let mut port = match open_exclusive(&b0xx_port.port_name, port_builder) {
+        Ok(port) => port,
+        Err(e) => return Err(open_error(b0xx_port.port_name, e)),
+    };
 
-    let (tx, rx) = crossbeam_channel::bounded(1);
+    // Handshaking devices wait for the UI to take each report. Streaming ones don't, so their
+    // reports queue up while the UI is busy and it only draws the most recent one
+    let (tx, rx) = crossbeam_channel::bounded(if rts_handshake { 1 } else { STREAMING_QUEUE_LEN });
 
     std::thread::Builder::new()
         .name("b0xx_viewer_serial".into())
         .spawn(move || {
+            let _instance_lock = instance_lock;
+            if let Some(model) = fingerprinted_model {
+                info!("Device matches a B0XX {} fingerprint", model);
+                if tx.send(B0xxMessage::Model(model)).is_err() {
+                    return Ok(());
+                }
+            }
+
             let mut buf = Vec::with_capacity(25);
             let mut state = [B0xxReport::default(); 20];
 
-            let port_builder = serialport::new(&b0xx_port.port_name, 115_200)
-                .data_bits(serialport::DataBits::Eight)
-                .flow_control(serialport::FlowControl::Hardware)
-                .parity(serialport::Parity::None)
-                .stop_bits(serialport::StopBits::One)
-                .timeout(std::time::Duration::from_millis(500));
-
-            let mut port =
-                match port_builder.open() {
-                    Ok(port) => port,
-                    Err(e) => return tx.send(B0xxMessage::Error(e.into())),
-                };
-
+            crate::capture::note(
+                &capture,
+                &format!("opened {} with {:?}", b0xx_port.port_name, link),
+            );
 
-            exhaust_buffer(&mut port, &tx);
+            exhaust_buffer(&mut port, &tx, &capture);
 
             let mut port = std::io::BufReader::with_capacity(25, port);
 
             use std::io::BufRead as _;
             loop {
-                if let Err(e) = port.get_mut().write_request_to_send(true) {
-                    return tx.send(B0xxMessage::Error(e.into()));
+                if rts_handshake {
+                    if let Err(e) = port.get_mut().write_request_to_send(true) {
+                        return tx.send(B0xxMessage::Error(e.into()));
+                    }
                 }
 
                 let bytes_read: usize = match port.read_until(B0xxReport::End as u8, &mut buf).map_err(Into::into) {
@@ -152,30 +411,37 @@ pub fn start_serial_probe(
                     }
                 };
 
-                if let Err(e) = port.get_mut().write_request_to_send(false) {
-                    return tx.send(B0xxMessage::Error(e.into()));
+                if rts_handshake {
+                    if let Err(e) = port.get_mut().write_request_to_send(false) {
+                        return tx.send(B0xxMessage::Error(e.into()));
+                    }
                 }
 
                 trace!("Bytes read: {}", bytes_read);
+                crate::capture::record(&capture, &buf);
 
                 port.consume(bytes_read);
-                if bytes_read == 25 {
-                    let end_index = buf.iter().position(|item| *item == B0xxReport::End as u8).unwrap() - 4;
-                    let start_index = end_index - 20;
-                    trace!("Selected range: {}..{}", start_index, end_index);
-
-                    for i in start_index..end_index {
-                        state[i] = buf[i].into();
-                    }
-                } else {
-                    exhaust_buffer(port.get_mut(), &tx);
+                if !parse_report(&buf, &mut state) {
+                    exhaust_buffer(port.get_mut(), &tx, &capture);
                 }
 
                 buf.clear();
 
-                if tx.send(B0xxMessage::State(state.into())).is_err() {
-                    info!("Reconnection detected, exiting runloop");
-                    return Ok(());
+                if rts_handshake {
+                    if tx.send(B0xxMessage::State(state.into())).is_err() {
+                        info!("Reconnection detected, exiting runloop");
+                        return Ok(());
+                    }
+                } else {
+                    // Never block a streaming device, or stale reports pile up in the OS buffer.
+                    // Once the queue is full, the UI is stalled and drains it as soon as it's back:
+                    // the next report after that carries the latest state
+                    if let Err(crossbeam_channel::TrySendError::Disconnected(_)) =
+                        tx.try_send(B0xxMessage::State(state.into()))
+                    {
+                        info!("Reconnection detected, exiting runloop");
+                        return Ok(());
+                    }
                 }
             }
         })?;
@@ -185,10 +451,10 @@ pub fn start_serial_probe(
 
 #[cfg(feature = "fake_serial")]
 pub fn start_serial_probe(
-    _: &Option<String>,
+    options: &ProbeOptions,
 ) -> Result<crossbeam_channel::Receiver<B0xxMessage>, ViewerError> {
     let (tx, rx) = crossbeam_channel::bounded(1);
-    if std::env::var("RELAX_ARDUINO_DETECT").is_ok() {
+    if options.detection.relax_arduino {
         info!("{:#?}", *ARDUINO_WHITELIST)
     }
     std::thread::spawn(move || loop {
@@ -200,14 +466,93 @@ pub fn start_serial_probe(
     Ok(rx)
 }
 
+/// Opens the port and prevents any other program from opening it while we hold it
+#[cfg(all(unix, not(feature = "fake_serial")))]
+fn open_exclusive(
+    port_name: &str,
+    builder: serialport::SerialPortBuilder,
+) -> serialport::Result<Box<dyn serialport::SerialPort>> {
+    // serialport doesn't keep the errno of a failed open, try it first to learn why it fails.
+    // A port held exclusively by another program fails with EBUSY
+    use std::os::unix::fs::OpenOptionsExt as _;
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK)
+        .open(port_name)?;
+
+    let mut port = builder.open_native()?;
+    port.set_exclusive(true)?;
+    Ok(Box::new(port))
+}
+
+/// COM ports can only be opened once, they are always exclusive
+#[cfg(all(windows, not(feature = "fake_serial")))]
+fn open_exclusive(
+    _port_name: &str,
+    builder: serialport::SerialPortBuilder,
+) -> serialport::Result<Box<dyn serialport::SerialPort>> {
+    builder.open()
+}
+
+#[cfg_attr(feature = "fake_serial", allow(dead_code))]
+fn is_port_busy(e: &serialport::Error) -> bool {
+    match e.kind() {
+        // The port was just listed, so Windows reporting it missing means someone else holds it
+        serialport::ErrorKind::NoDevice => cfg!(windows),
+        serialport::ErrorKind::Io(std::io::ErrorKind::ResourceBusy) => true,
+        _ => false,
+    }
+}
+
+/// Turns a failure to open `port_name` into an error telling the user what to do about it
+#[cfg_attr(feature = "fake_serial", allow(dead_code))]
+fn open_error(port_name: String, e: serialport::Error) -> ViewerError {
+    match e.kind() {
+        serialport::ErrorKind::Io(std::io::ErrorKind::PermissionDenied) => {
+            ViewerError::SerialPermissionDenied {
+                guidance: crate::permissions::diagnose(&port_name),
+                port: port_name,
+            }
+        }
+        _ if is_port_busy(&e) => ViewerError::PortBusy(port_name),
+        _ => e.into(),
+    }
+}
+
+/// Decodes a complete 25-byte report into `state`, returns false if the report was partial
+pub fn parse_report(buf: &[u8], state: &mut [B0xxReport; 20]) -> bool {
+    if buf.len() != 25 {
+        return false;
+    }
+
+    let end_index = match buf.iter().position(|item| *item == B0xxReport::End as u8) {
+        Some(position) if position >= 24 => position - 4,
+        _ => return false,
+    };
+    let start_index = end_index - 20;
+    trace!("Selected range: {}..{}", start_index, end_index);
+
+    for i in start_index..end_index {
+        state[i] = buf[i].into();
+    }
+
+    true
+}
+
 #[allow(dead_code)]
 #[inline(always)]
-fn exhaust_buffer(port: &mut Box<dyn serialport::SerialPort>, tx: &crossbeam_channel::Sender<B0xxMessage>) {
+fn exhaust_buffer(
+    port: &mut Box<dyn serialport::SerialPort>,
+    tx: &crossbeam_channel::Sender<B0xxMessage>,
+    capture: &Option<SharedCapture>,
+) {
     // Exhaust the initial buffer till we find the end of a report and consume it.
     // This is caused by a UB in Windows' COM port handling causing partial reports
     // sometimes
     trace!("Buffer exhaustion started");
     let mut exhaust_buffer = [0u8; 1];
+    let mut exhausted = vec![];
     use std::io::Read as _;
     loop {
         if let Err(e) = port
@@ -219,13 +564,174 @@ fn exhaust_buffer(port: &mut Box<dyn serialport::SerialPort>, tx: &crossbeam_cha
             break;
         }
 
+        exhausted.push(exhaust_buffer[0]);
         if exhaust_buffer[0] == B0xxReport::End as u8 {
             trace!("Buffer exhausted successfully, continuing...");
             break;
         }
     }
 
+    crate::capture::record(capture, &exhausted);
+
     if let Err(e) = port.clear(serialport::ClearBuffer::All) {
         let _ = tx.send(B0xxMessage::Error(e.into()));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usb_port(vid: u16, pid: u16, product: Option<&str>) -> serialport::SerialPortInfo {
+        serialport::SerialPortInfo {
+            port_name: "/dev/ttyACM0".into(),
+            port_type: serialport::SerialPortType::UsbPort(serialport::UsbPortInfo {
+                vid,
+                pid,
+                serial_number: Some("8543932313335170E0E1".into()),
+                manufacturer: None,
+                product: product.map(Into::into),
+            }),
+        }
+    }
+
+    fn rule(vid: Option<&str>, pid: Option<&str>, product: Option<&str>) -> UsbRule {
+        UsbRule {
+            vid: vid.map(Into::into),
+            pid: pid.map(Into::into),
+            product: product.map(Into::into),
+            serial_number: None,
+        }
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(wildcard_match("B0XX", "B0XX"));
+        assert!(!wildcard_match("B0XX", "B0XX R2"));
+        assert!(wildcard_match("B0XX*", "B0XX R2"));
+        assert!(wildcard_match("*R2", "B0XX R2"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("B0XX R?", "B0XX R4"));
+        assert!(!wildcard_match("B0XX R?", "B0XX R"));
+        assert!(wildcard_match("*a*b?", "xxaybbz"));
+        assert!(!wildcard_match("*a*b?", "xxayb"));
+        // Descriptors are compared as reported by the device
+        assert!(!wildcard_match("b0xx*", "B0XX R2"));
+    }
+
+    #[test]
+    fn usb_ids_are_hex() {
+        use std::convert::TryFrom as _;
+        for (vid, pid) in [("0x2341", "0x8036"), ("0X2341", "0X8036"), ("2341", "8036")] {
+            let matcher = UsbMatcher::try_from(rule(Some(vid), Some(pid), None)).unwrap();
+            assert_eq!((matcher.vid, matcher.pid), (Some(0x2341), Some(0x8036)));
+        }
+        let matcher = UsbMatcher::try_from(rule(Some("0x2a03"), Some("0X00aB"), None)).unwrap();
+        assert_eq!((matcher.vid, matcher.pid), (Some(0x2A03), Some(0xAB)));
+
+        assert!(UsbMatcher::try_from(rule(Some("0xZZZZ"), None, None)).is_err());
+        assert!(UsbMatcher::try_from(rule(Some("0x12345"), None, None)).is_err());
+        assert!(compile_rule("detection", &rule(Some("nope"), None, None)).is_none());
+        assert!(compile_rule("detection", &UsbRule::default()).is_none());
+    }
+
+    #[test]
+    fn usb_rules_match_every_criterion() {
+        use std::convert::TryFrom as _;
+        let matcher = UsbMatcher::try_from(rule(Some("0x1209"), None, Some("B0XX*"))).unwrap();
+        let matches = |port: serialport::SerialPortInfo| match port.port_type {
+            serialport::SerialPortType::UsbPort(info) => matcher.matches(&info),
+            _ => unreachable!(),
+        };
+        assert!(matches(usb_port(0x1209, 0x0001, Some("B0XX R4"))));
+        assert!(!matches(usb_port(0x1209, 0x0001, Some("Arduino Micro"))));
+        assert!(!matches(usb_port(0x1209, 0x0001, None)));
+        assert!(!matches(usb_port(0x16C0, 0x0001, Some("B0XX R4"))));
+    }
+
+    #[test]
+    fn user_rules_extend_the_builtin_ones() {
+        let leonardo = usb_port(0x2341, 0x8036, None);
+        let yun = usb_port(0x2341, 0x8041, None);
+        let custom = usb_port(0x1209, 0xB0CC, Some("B0XX R4"));
+
+        let policy = DetectionPolicy::new(None, false, &[], &[]);
+        assert!(policy.matches(&leonardo));
+        assert!(policy.matches(&usb_port(0x1234, 0x5678, Some("Arduino_Leonardo"))));
+        assert!(!policy.matches(&yun));
+        assert!(!policy.matches(&custom));
+
+        let policy = DetectionPolicy::new(
+            None,
+            false,
+            &[rule(Some("0x1209"), Some("0xb0cc"), None)],
+            &[],
+        );
+        assert!(policy.matches(&leonardo));
+        assert!(policy.matches(&custom));
+        assert!(!policy.matches(&yun));
+
+        // The Arduino whitelist only applies when relaxed, on top of the user rules
+        let policy = DetectionPolicy::new(None, true, &[rule(None, None, Some("B0XX*"))], &[]);
+        assert!(policy.matches(&leonardo));
+        assert!(policy.matches(&yun));
+        assert!(policy.matches(&custom));
+        assert!(!policy.matches(&usb_port(0x1234, 0x5678, None)));
+
+        // A custom TTY bypasses every rule
+        let policy = DetectionPolicy::new(Some("/dev/ttyUSB3".into()), true, &[], &[]);
+        assert!(!policy.matches(&leonardo));
+        assert!(policy.matches(&serialport::SerialPortInfo {
+            port_name: "/dev/ttyUSB3".into(),
+            port_type: serialport::SerialPortType::Unknown,
+        }));
+    }
+
+    #[test]
+    fn fingerprints() {
+        let fingerprint = ModelFingerprint {
+            device: rule(Some("0x1209"), None, None),
+            model: B0xxModel::R2,
+        };
+        let policy = DetectionPolicy::new(None, false, &[], &[fingerprint]);
+        assert_eq!(
+            policy.fingerprint(&usb_port(0x1209, 0xB0CC, None)),
+            Some(B0xxModel::R2)
+        );
+        assert_eq!(policy.fingerprint(&usb_port(0x2341, 0x8036, None)), None);
+    }
+
+    #[test]
+    fn busy_ports() {
+        use serialport::{Error, ErrorKind};
+        let busy = |e: std::io::Error| is_port_busy(&Error::from(e));
+        #[cfg(unix)]
+        assert!(busy(std::io::Error::from_raw_os_error(libc::EBUSY)));
+        #[cfg(unix)]
+        assert!(!busy(std::io::Error::from_raw_os_error(libc::EIO)));
+        assert!(!busy(std::io::ErrorKind::PermissionDenied.into()));
+        // Only the error kind counts, whatever the message says
+        assert!(!is_port_busy(&Error::new(
+            ErrorKind::Unknown,
+            "Device or resource busy"
+        )));
+        assert_eq!(
+            is_port_busy(&Error::new(ErrorKind::NoDevice, "Not found")),
+            cfg!(windows)
+        );
+
+        let e = Error::from(std::io::Error::from(std::io::ErrorKind::ResourceBusy));
+        match open_error("/dev/ttyACM0".into(), e) {
+            ViewerError::PortBusy(port) => assert_eq!(port, "/dev/ttyACM0"),
+            other => panic!("expected a busy port, got {:?}", other),
+        }
+        let broken = Error::new(ErrorKind::InvalidInput, "Invalid baud rate");
+        assert!(matches!(
+            open_error("/dev/ttyACM0".into(), broken),
+            ViewerError::SerialPortError(_)
+        ));
+        assert!(ViewerError::PortBusy("COM3".into())
+            .to_string()
+            .starts_with("COM3 is already in use by another program"));
+    }
+}
@@ -0,0 +1,88 @@
+use crate::config::ViewerAnimations;
+
+/// When each button last changed state, in `BUTTON_NAMES` order
+#[derive(Debug, Clone, Default)]
+pub struct ButtonAnimations {
+    changed_at: [Option<std::time::Instant>; 20],
+}
+
+impl ButtonAnimations {
+    /// Starts the transitions of the buttons that changed between `previous` and `current`
+    pub fn update(&mut self, previous: [bool; 20], current: [bool; 20], now: std::time::Instant) {
+        for (i, changed_at) in self.changed_at.iter_mut().enumerate() {
+            if previous[i] != current[i] {
+                *changed_at = Some(now);
+            }
+        }
+    }
+
+    /// How far along a transition of `duration` milliseconds button `i` is, from 0 when it just
+    /// changed state to 1 once the transition is over
+    pub fn progress(&self, i: usize, duration: u64, now: std::time::Instant) -> f64 {
+        match self.changed_at[i] {
+            Some(changed_at) if duration > 0 => {
+                let elapsed = now.saturating_duration_since(changed_at).as_secs_f64();
+                (elapsed * 1000. / duration as f64).min(1.)
+            }
+            _ => 1.,
+        }
+    }
+
+    /// Whether a transition is still running, so frames must keep coming without input changes
+    pub fn is_running(&self, animations: &ViewerAnimations, now: std::time::Instant) -> bool {
+        let longest = animations.longest();
+        self.changed_at
+            .iter()
+            .flatten()
+            .any(|changed_at| now.saturating_duration_since(*changed_at) < longest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: std::time::Duration = std::time::Duration::from_millis(1);
+
+    fn pressed(index: usize) -> [bool; 20] {
+        let mut state = [false; 20];
+        state[index] = true;
+        state
+    }
+
+    #[test]
+    fn progress() {
+        let start = std::time::Instant::now();
+        let mut animations = ButtonAnimations::default();
+        assert_eq!(animations.progress(0, 200, start), 1.);
+
+        animations.update([false; 20], pressed(0), start);
+        assert_eq!(animations.progress(0, 200, start), 0.);
+        assert_eq!(animations.progress(0, 200, start + 50 * MS), 0.25);
+        assert_eq!(animations.progress(0, 200, start + 500 * MS), 1.);
+        // Untouched buttons and disabled effects are done
+        assert_eq!(animations.progress(1, 200, start), 1.);
+        assert_eq!(animations.progress(0, 0, start), 1.);
+
+        // Releasing restarts the transition
+        animations.update(pressed(0), [false; 20], start + 100 * MS);
+        assert_eq!(animations.progress(0, 200, start + 150 * MS), 0.25);
+    }
+
+    #[test]
+    fn running() {
+        let start = std::time::Instant::now();
+        let animation = ViewerAnimations {
+            fade_out: 100,
+            glow: 300,
+            ..Default::default()
+        };
+        let mut animations = ButtonAnimations::default();
+        assert!(!animations.is_running(&animation, start));
+
+        animations.update([false; 20], pressed(3), start);
+        assert!(animations.is_running(&animation, start + 200 * MS));
+        assert!(!animations.is_running(&animation, start + 300 * MS));
+        assert!(!animations.is_running(&ViewerAnimations::default(), start));
+    }
+}
@@ -1,19 +1,16 @@
-use crate::b0xx_state::B0xxState;
+use crate::b0xx_state::{B0xxModel, B0xxState};
+use super::animation::ButtonAnimations;
+use crate::config::{theme_warnings, ConfigLayer, FileMigration, LayeredConfig, ViewerOptions};
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
 pub enum ViewerAppStatus {
     Running,
     Reconnecting,
     NeedsReconnection,
+    #[default]
     Undefined,
 }
 
-impl Default for ViewerAppStatus {
-    fn default() -> Self {
-        ViewerAppStatus::Undefined
-    }
-}
-
 impl ViewerAppStatus {
     pub fn set_running(&mut self) {
         if *self != ViewerAppStatus::Running {
@@ -26,9 +23,11 @@ impl ViewerAppStatus {
 #[cfg_attr(not(feature = "fps"), derive(Clone))]
 pub struct ViewerApp {
     pub state: B0xxState,
+    pub detected_model: Option<B0xxModel>,
     pub status: ViewerAppStatus,
     pub is_draggable: bool,
     pub is_dragged: bool,
+    pub animations: ButtonAnimations,
     #[cfg(feature = "fps")]
     pub fps: fps_counter::FPSCounter,
 }
@@ -39,7 +38,301 @@ impl ViewerApp {
             return false;
         }
 
+        if new_state.has_r2_inputs() {
+            self.set_detected_model(B0xxModel::R2);
+        }
+
+        self.animations.update(
+            self.state.to_array(),
+            new_state.to_array(),
+            std::time::Instant::now(),
+        );
         self.state = new_state;
         true
     }
+
+    /// Forgets what was learned from the previous device, another controller may be plugged in.
+    /// Its model is detected again from its fingerprint and reports
+    pub fn disconnected(&mut self) {
+        self.detected_model = None;
+        self.state = B0xxState::default();
+    }
+
+    pub fn set_detected_model(&mut self, model: B0xxModel) -> bool {
+        if self.detected_model == Some(model) {
+            return false;
+        }
+
+        info!("Detected a B0XX {}, updating layout", model);
+        self.detected_model = Some(model);
+        true
+    }
+}
+
+/// Keyboard shortcuts switching configuration profiles: Tab cycles through them,
+/// 1-9 select one by its position and 0 goes back to the base configuration
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ProfileHotkey {
+    Next,
+    Select(usize),
+}
+
+impl ProfileHotkey {
+    pub fn from_key(key: glium::glutin::event::VirtualKeyCode) -> Option<Self> {
+        use glium::glutin::event::VirtualKeyCode::*;
+        let index = match key {
+            Tab => return Some(ProfileHotkey::Next),
+            Key0 | Numpad0 => 0,
+            Key1 | Numpad1 => 1,
+            Key2 | Numpad2 => 2,
+            Key3 | Numpad3 => 3,
+            Key4 | Numpad4 => 4,
+            Key5 | Numpad5 => 5,
+            Key6 | Numpad6 => 6,
+            Key7 | Numpad7 => 7,
+            Key8 | Numpad8 => 8,
+            Key9 | Numpad9 => 9,
+            _ => return None,
+        };
+
+        Some(ProfileHotkey::Select(index))
+    }
+}
+
+/// Applies a profile hotkey, returning the new options if the active profile changed
+pub fn switch_profile(config: &mut LayeredConfig, hotkey: ProfileHotkey) -> Option<ViewerOptions> {
+    let names = config.profile_names();
+    if names.is_empty() {
+        return None;
+    }
+
+    let current = config.active_profile();
+    let target = match hotkey {
+        ProfileHotkey::Next => match current
+            .as_ref()
+            .and_then(|current| names.iter().position(|name| name == current))
+        {
+            Some(index) => names.get(index + 1).cloned(),
+            None => names.first().cloned(),
+        },
+        ProfileHotkey::Select(0) => None,
+        ProfileHotkey::Select(index) => Some(names.get(index - 1)?.clone()),
+    };
+
+    if target == current {
+        return None;
+    }
+
+    let previous = config.runtime.clone();
+    config.select_profile(target.as_deref());
+    match config.resolve() {
+        Ok(resolved) => {
+            info!(
+                "Switched to profile {}",
+                target.as_deref().unwrap_or("(none)")
+            );
+            for warning in theme_warnings(&resolved.options) {
+                warn!("Theme: {}", warning);
+            }
+            Some(resolved.options)
+        }
+        Err(e) => {
+            error!("{}", e);
+            config.runtime = previous;
+            None
+        }
+    }
+}
+
+/// Reloads the configuration file after it changed on disk. The previous configuration
+/// stays in place if the new one can't be loaded
+pub fn reload_config(config: &mut LayeredConfig, current: &ViewerOptions) -> Option<ViewerOptions> {
+    let path = config.file.as_ref()?.0.clone();
+    // The file may be open in an editor, never rewrite it from under the user
+    let layer = match ConfigLayer::from_file(&path, FileMigration::InMemory) {
+        Ok(layer) => layer,
+        Err(e) => {
+            error!("Could not reload {}: {}", path.display(), e);
+            return None;
+        }
+    };
+
+    let previous = config.file.replace((path.clone(), layer));
+    if let Some(name) = config.unknown_profile() {
+        warn!("Unknown profile `{}`, ignoring it", name);
+    }
+    match config.resolve() {
+        Ok(resolved) => {
+            info!("Reloaded configuration from {}", path.display());
+            if resolved.options.probe_settings_differ(current) {
+                warn!("Serial connection and detection settings take effect after a restart");
+            }
+            for warning in theme_warnings(&resolved.options) {
+                warn!("Theme: {}", warning);
+            }
+            Some(resolved.options)
+        }
+        Err(e) => {
+            error!("Could not reload {}: {}", path.display(), e);
+            config.file = previous;
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ViewerModelSelection;
+
+    fn ls_pressed() -> B0xxState {
+        B0xxState {
+            mod_ls: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn r2_is_detected_from_its_inputs() {
+        let options = ViewerOptions::default();
+        assert_eq!(options.model, ViewerModelSelection::Auto);
+
+        let mut app = ViewerApp::default();
+        assert!(app.update_state(B0xxState {
+            a: true,
+            ..Default::default()
+        }));
+        assert_eq!(app.detected_model, None);
+        assert_eq!(options.effective_model(app.detected_model), B0xxModel::R1);
+
+        assert!(app.update_state(ls_pressed()));
+        assert_eq!(app.detected_model, Some(B0xxModel::R2));
+        assert!(!app.update_state(ls_pressed()));
+        assert!(!app.set_detected_model(B0xxModel::R2));
+
+        // Releasing LS doesn't bring the r1 layout back
+        assert!(app.update_state(B0xxState::default()));
+        assert_eq!(options.effective_model(app.detected_model), B0xxModel::R2);
+    }
+
+    #[test]
+    fn explicit_models_override_detection() {
+        let mut app = ViewerApp::default();
+        app.update_state(ls_pressed());
+
+        let mut options = ViewerOptions::default();
+        options.model = ViewerModelSelection::R1;
+        assert_eq!(options.effective_model(app.detected_model), B0xxModel::R1);
+
+        options.model = ViewerModelSelection::R2;
+        assert_eq!(options.effective_model(None), B0xxModel::R2);
+    }
+
+    #[test]
+    fn disconnecting_resets_detection() {
+        let mut app = ViewerApp::default();
+        app.update_state(ls_pressed());
+        app.disconnected();
+        assert_eq!(app.detected_model, None);
+        assert_eq!(app.state, B0xxState::default());
+        assert_eq!(
+            ViewerOptions::default().effective_model(app.detected_model),
+            B0xxModel::R1
+        );
+
+        // Another device plugged in gets detected again
+        assert!(app.update_state(ls_pressed()));
+        assert_eq!(app.detected_model, Some(B0xxModel::R2));
+    }
+
+    fn profiles(extra: &str) -> LayeredConfig {
+        let path = std::path::PathBuf::from("b0xx_viewer_config.toml");
+        let values = toml::from_str(&format!(
+            "[profiles.practice]\ncolored_rims = true\n[profiles.stream]\ndisplay_labels = true\n{}",
+            extra
+        ))
+        .unwrap();
+        LayeredConfig {
+            file: Some((path.clone(), ConfigLayer::from_file_table(&path, values))),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn tab_cycles_through_profiles() {
+        let mut config = profiles("");
+        let mut cycle =
+            || switch_profile(&mut config, ProfileHotkey::Next).map(|options| options.profile);
+        assert_eq!(cycle(), Some(Some("practice".into())));
+        assert_eq!(cycle(), Some(Some("stream".into())));
+        // Past the last profile comes the base configuration, then the first profile again
+        assert_eq!(cycle(), Some(None));
+        assert_eq!(cycle(), Some(Some("practice".into())));
+
+        let mut config = LayeredConfig::default();
+        assert!(switch_profile(&mut config, ProfileHotkey::Next).is_none());
+    }
+
+    #[test]
+    fn number_keys_select_profiles() {
+        let mut config = profiles("");
+        assert!(switch_profile(&mut config, ProfileHotkey::Select(0)).is_none());
+
+        let options = switch_profile(&mut config, ProfileHotkey::Select(2)).unwrap();
+        assert_eq!(options.profile.as_deref(), Some("stream"));
+        assert!(options.display_labels);
+        assert!(switch_profile(&mut config, ProfileHotkey::Select(2)).is_none());
+
+        assert!(switch_profile(&mut config, ProfileHotkey::Select(3)).is_none());
+        assert!(switch_profile(&mut config, ProfileHotkey::Select(9)).is_none());
+        assert_eq!(config.active_profile().as_deref(), Some("stream"));
+
+        let options = switch_profile(&mut config, ProfileHotkey::Select(0)).unwrap();
+        assert_eq!(options.profile, None);
+        assert!(!options.display_labels);
+    }
+
+    #[test]
+    fn broken_profiles_are_rolled_back() {
+        let mut config = profiles("[profiles.broken]\nscale = \"big\"");
+        switch_profile(&mut config, ProfileHotkey::Select(3)).unwrap();
+        let runtime = config.runtime.clone();
+
+        assert!(switch_profile(&mut config, ProfileHotkey::Select(1)).is_none());
+        assert_eq!(config.runtime, runtime);
+        assert_eq!(config.active_profile().as_deref(), Some("stream"));
+    }
+
+    #[test]
+    fn reloading_leaves_old_files_alone() {
+        let dir = std::env::temp_dir().join(format!("b0xx_viewer_reload_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("b0xx_viewer_config.toml");
+        std::fs::write(&path, "version = 2\n").unwrap();
+        let mut config = LayeredConfig {
+            file: Some((
+                path.clone(),
+                ConfigLayer::from_file(&path, FileMigration::Rewrite).unwrap(),
+            )),
+            ..Default::default()
+        };
+        let current = config.resolve().unwrap().options;
+
+        let snippet = "# pasted from an old setup\nis_r2_b0xx = true\n";
+        std::fs::write(&path, snippet).unwrap();
+        let options = reload_config(&mut config, &current).unwrap();
+        assert_eq!(options.model, ViewerModelSelection::R2);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), snippet);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        // A broken save keeps the previous configuration
+        std::fs::write(&path, "scale = \"big\"\n").unwrap();
+        assert!(reload_config(&mut config, &options).is_none());
+        assert_eq!(
+            config.resolve().unwrap().options.model,
+            ViewerModelSelection::R2
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,32 @@
+use crate::config::ViewerOptions;
+
+/// Fonts loaded from the configuration, by path. conrod can't unload fonts, so each file is only
+/// read the first time it's used
+#[derive(Debug, Default)]
+pub struct FontCache {
+    loaded: std::collections::HashMap<std::path::PathBuf, Option<conrod_core::text::font::Id>>,
+}
+
+impl FontCache {
+    /// The font to draw labels with, `None` stands for the theme's font
+    pub fn label_font(
+        &mut self,
+        fonts: &mut conrod_core::text::font::Map,
+        options: &ViewerOptions,
+    ) -> Option<conrod_core::text::font::Id> {
+        let path = options.asset_path(options.font.path.as_deref()?);
+        *self.loaded.entry(path.clone()).or_insert_with(|| {
+            match conrod_core::text::font::from_file(&path) {
+                Ok(font) => Some(fonts.insert(font)),
+                Err(e) => {
+                    warn!(
+                        "Could not load font {}, using the default font: {}",
+                        path.display(),
+                        e
+                    );
+                    None
+                }
+            }
+        })
+    }
+}
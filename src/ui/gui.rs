@@ -1,5 +1,6 @@
-use super::{app::*, Ids};
-use crate::config::ViewerOptions;
+use super::{app::*, skin::SkinImages, Ids};
+use crate::b0xx_state::B0xxModel;
+use crate::config::{ButtonShape, LabelPosition, ShapeKind, ViewerOptions, BUTTON_NAMES};
 use crate::ui::support::{BTN_RADIUS, WIN_H, WIN_W};
 
 pub fn theme() -> conrod_core::Theme {
@@ -24,440 +25,254 @@ pub fn theme() -> conrod_core::Theme {
     }
 }
 
+/// Centers of the buttons in `BUTTON_NAMES` order, relative to the center of a `WIN_W`×`WIN_H` window
+const BUTTON_POSITIONS: [[f64; 2]; 20] = [
+    [0., 40.],     // start
+    [142., 102.],  // y
+    [142., 60.],   // x
+    [100., 45.],   // b
+    [90., -55.],   // a
+    [-232., 33.],  // l
+    [100., 87.],   // r
+    [185., 55.],   // z
+    [227., 37.],   // up
+    [-147., 60.],  // down
+    [-105., 45.],  // right
+    [-192., 55.],  // left
+    [-100., -45.], // mod_x
+    [-62., -67.],  // mod_y
+    [57., -31.],   // c_left
+    [125., -31.],  // c_right
+    [91., -7.],    // c_up
+    [57., -79.],   // c_down
+    [185., 97.],   // mod_ls
+    [225., 80.],   // mod_ms
+];
+
+/// Space between a button and a label drawn above or below it
+const LABEL_GAP: f64 = 2.;
+
+/// Opacity of the glow around a pressed button, once it has fully faded in
+const GLOW_OPACITY: f64 = 0.4;
+
+/// LS and MS come last and only exist on r2 units
+const R1_BUTTON_COUNT: usize = 18;
+
+/// Horizontal and vertical factors applied to the layout so it fills the window
+fn layout_scale(ui: &conrod_core::Ui, keep_aspect_ratio: bool) -> (f64, f64) {
+    let (x, y) = (ui.win_w / WIN_W as f64, ui.win_h / WIN_H as f64);
+    if keep_aspect_ratio {
+        (x.min(y), x.min(y))
+    } else {
+        (x, y)
+    }
+}
+
 pub fn render_gui(
     ui: &mut conrod_core::UiCell,
     ids: &Ids,
     app: &mut ViewerApp,
     options: &ViewerOptions,
+    skin: &SkinImages,
+    label_font: Option<conrod_core::text::font::Id>,
 ) {
     use conrod_core::{widget, Colorable, Positionable, Sizeable, Widget};
 
-    // Compute button margin only if necessary
-    let btn_label_margin = if options.display_labels {
-        BTN_RADIUS / 2. - ui.theme().font_size_small as f64
+    let (scale_x, scale_y) = layout_scale(ui, options.keep_aspect_ratio);
+    let scale = scale_x.min(scale_y);
+    let font_size = |size: conrod_core::FontSize| (size as f64 * scale).round() as conrod_core::FontSize;
+
+    let canvas_color = if options.transparent {
+        conrod_core::color::TRANSPARENT
     } else {
-        0.
+        options.background_color.into()
     };
 
     widget::Canvas::new()
-        .color(options.background_color.into())
-        .w_h(WIN_W.into(), WIN_H.into())
+        .color(canvas_color)
+        .w_h(ui.win_w, ui.win_h)
         .x_y(0., 0.)
         .crop_kids()
         .set(ids.frame, ui);
 
+    if let Some(background) = skin.background {
+        widget::Image::new(background)
+            .w_h(WIN_W as f64 * scale_x, WIN_H as f64 * scale_y)
+            .middle_of(ids.frame)
+            .graphics_for(ids.frame)
+            .set(ids.background_image, ui);
+    }
+
+    if options.case.points.len() >= 3 {
+        let [x, y] = polygon_center(&options.case.points);
+        let points = scale_points(&options.case.points, (scale_x, scale_y));
+        let outline = options.case.outline.map(|color| {
+            widget::primitive::line::Style::solid()
+                .color(color.into())
+                .thickness(2. * scale)
+        });
+
+        if let Some(color) = options.case.color {
+            widget::Polygon::centred_fill_with(points.clone(), color.into())
+                .x_y_relative_to(ids.frame, x * scale_x, y * scale_y)
+                .parent(ids.frame)
+                .graphics_for(ids.frame)
+                .set(ids.case_fill, ui);
+        }
+
+        if let Some(outline) = outline {
+            widget::Polygon::centred_outline_styled(points, outline)
+                .x_y_relative_to(ids.frame, x * scale_x, y * scale_y)
+                .parent(ids.frame)
+                .graphics_for(ids.frame)
+                .set(ids.case_outline, ui);
+        }
+    }
+
     if app.status == ViewerAppStatus::Reconnecting
         || app.status == ViewerAppStatus::NeedsReconnection
     {
         conrod_core::widget::Rectangle::fill_with(
-            [WIN_W.into(), WIN_H.into()],
+            [ui.win_w, ui.win_h],
             conrod_core::color::BLACK.with_alpha(0.8),
         )
-        .w_h(WIN_W.into(), WIN_H.into())
+        .w_h(ui.win_w, ui.win_h)
         .x_y(0., 0.)
         .crop_kids()
         .set(ids.reconnect_bg, ui);
 
         conrod_core::widget::Text::new("Reconnecting...")
             .color(conrod_core::color::WHITE)
+            .font_size(font_size(ui.theme().font_size_medium))
             .middle_of(ids.reconnect_bg)
             .set(ids.reconnect_label, ui);
     }
 
-    let (btn, mut m_text) = make_button(
-        app.state.start,
-        ids.frame,
-        options.button_active_colors.start,
-        options.button_inactive_colors.start,
-        options.background_color,
-        options.display_labels,
-        options.colored_rims,
-    );
-
-    btn.x_y(0., 40.).set(ids.start_btn, ui);
-
-    if let Some(text_color) = m_text.take() {
-        conrod_core::widget::Text::new("SRT")
-            .color(text_color)
-            .mid_top_with_margin_on(ids.start_btn, btn_label_margin)
-            .set(ids.start_label, ui);
-    }
-
-    let (btn, mut m_text) = make_button(
-        app.state.right,
-        ids.frame,
-        options.button_active_colors.right,
-        options.button_inactive_colors.right,
-        options.background_color,
-        options.display_labels,
-        options.colored_rims,
-    );
-
-    btn.x_y_relative_to(ids.start_btn, -105., 5.)
-        .set(ids.right_btn, ui);
-
-    if let Some(text_color) = m_text.take() {
-        conrod_core::widget::Text::new("→")
-            .color(text_color)
-            .mid_top_with_margin_on(ids.right_btn, btn_label_margin)
-            .set(ids.right_label, ui);
-    }
-
-    let (btn, mut m_text) = make_button(
-        app.state.down,
-        ids.frame,
-        options.button_active_colors.down,
-        options.button_inactive_colors.down,
-        options.background_color,
-        options.display_labels,
-        options.colored_rims,
-    );
-
-    btn.x_y_relative_to(ids.right_btn, -42., 15.)
-        .set(ids.down_btn, ui);
-
-    if let Some(text_color) = m_text.take() {
-        conrod_core::widget::Text::new("↓")
-            .color(text_color)
-            .mid_top_with_margin_on(ids.down_btn, btn_label_margin)
-            .set(ids.down_label, ui);
-    }
-
-    let (btn, mut m_text) = make_button(
-        app.state.left,
-        ids.frame,
-        options.button_active_colors.left,
-        options.button_inactive_colors.left,
-        options.background_color,
-        options.display_labels,
-        options.colored_rims,
-    );
-
-    btn.x_y_relative_to(ids.down_btn, -45., -5.)
-        .set(ids.left_btn, ui);
-
-    if let Some(text_color) = m_text.take() {
-        conrod_core::widget::Text::new("←")
-            .color(text_color)
-            .mid_top_with_margin_on(ids.left_btn, btn_label_margin)
-            .set(ids.left_label, ui);
-    }
-
-    let (btn, mut m_text) = make_button(
-        app.state.l,
-        ids.frame,
-        options.button_active_colors.l,
-        options.button_inactive_colors.l,
-        options.background_color,
-        options.display_labels,
-        options.colored_rims,
-    );
-
-    btn.x_y_relative_to(ids.left_btn, -40., -22.)
-        .set(ids.l_btn, ui);
-
-    if let Some(text_color) = m_text.take() {
-        conrod_core::widget::Text::new("L")
-            .color(text_color)
-            .mid_top_with_margin_on(ids.l_btn, btn_label_margin)
-            .set(ids.l_label, ui);
-    }
-
-    let (btn, mut m_text) = make_button(
-        app.state.mod_x,
-        ids.frame,
-        options.button_active_colors.mod_x,
-        options.button_inactive_colors.mod_x,
-        options.background_color,
-        options.display_labels,
-        options.colored_rims,
-    );
-
-    btn.x_y_relative_to(ids.right_btn, 5., -90.)
-        .set(ids.mod_x_btn, ui);
-
-    if let Some(text_color) = m_text.take() {
-        conrod_core::widget::Text::new("MX")
-            .color(text_color)
-            .mid_top_with_margin_on(ids.mod_x_btn, btn_label_margin)
-            .set(ids.mod_x_label, ui);
-    }
-
-    let (btn, mut m_text) = make_button(
-        app.state.mod_y,
-        ids.frame,
-        options.button_active_colors.mod_y,
-        options.button_inactive_colors.mod_y,
-        options.background_color,
-        options.display_labels,
-        options.colored_rims,
-    );
-
-    btn.x_y_relative_to(ids.mod_x_btn, 38., -22.)
-        .set(ids.mod_y_btn, ui);
-
-    if let Some(text_color) = m_text.take() {
-        conrod_core::widget::Text::new("MY")
-            .color(text_color)
-            .mid_top_with_margin_on(ids.mod_y_btn, btn_label_margin)
-            .set(ids.mod_y_label, ui);
-    }
-
-    let (btn, mut m_text) = make_button(
-        app.state.b,
-        ids.frame,
-        options.button_active_colors.b,
-        options.button_inactive_colors.b,
-        options.background_color,
-        options.display_labels,
-        options.colored_rims,
-    );
-
-    btn.x_y_relative_to(ids.start_btn, 100., 5.)
-        .set(ids.b_btn, ui);
-
-    if let Some(text_color) = m_text.take() {
-        conrod_core::widget::Text::new("B")
-            .color(text_color)
-            .mid_top_with_margin_on(ids.b_btn, btn_label_margin)
-            .set(ids.b_label, ui);
-    }
-
-    let (btn, mut m_text) = make_button(
-        app.state.x,
-        ids.frame,
-        options.button_active_colors.x,
-        options.button_inactive_colors.x,
-        options.background_color,
-        options.display_labels,
-        options.colored_rims,
-    );
-
-    btn.x_y_relative_to(ids.b_btn, 42., 15.).set(ids.x_btn, ui);
-
-    if let Some(text_color) = m_text.take() {
-        conrod_core::widget::Text::new("X")
-            .color(text_color)
-            .mid_top_with_margin_on(ids.x_btn, btn_label_margin)
-            .set(ids.x_label, ui);
-    }
-
-    let (btn, mut m_text) = make_button(
-        app.state.z,
-        ids.frame,
-        options.button_active_colors.z,
-        options.button_inactive_colors.z,
-        options.background_color,
-        options.display_labels,
-        options.colored_rims,
-    );
-
-    btn.x_y_relative_to(ids.x_btn, 43., -5.).set(ids.z_btn, ui);
-
-    if let Some(text_color) = m_text.take() {
-        conrod_core::widget::Text::new("Z")
-            .color(text_color)
-            .mid_top_with_margin_on(ids.z_btn, btn_label_margin)
-            .set(ids.z_label, ui);
-    }
-
-    let (btn, mut m_text) = make_button(
-        app.state.up,
-        ids.frame,
-        options.button_active_colors.up,
-        options.button_inactive_colors.up,
-        options.background_color,
-        options.display_labels,
-        options.colored_rims,
-    );
-
-    btn.x_y_relative_to(ids.z_btn, 42., -18.)
-        .set(ids.up_btn, ui);
-
-    if let Some(text_color) = m_text.take() {
-        conrod_core::widget::Text::new("↑")
-            .color(text_color)
-            .mid_top_with_margin_on(ids.up_btn, btn_label_margin)
-            .set(ids.up_label, ui);
-    }
-
-    let (btn, mut m_text) = make_button(
-        app.state.y,
-        ids.frame,
-        options.button_active_colors.y,
-        options.button_inactive_colors.y,
-        options.background_color,
-        options.display_labels,
-        options.colored_rims,
-    );
-
-    btn.x_y_relative_to(ids.x_btn, 0., 42.).set(ids.y_btn, ui);
-
-    if let Some(text_color) = m_text.take() {
-        conrod_core::widget::Text::new("Y")
-            .color(text_color)
-            .mid_top_with_margin_on(ids.y_btn, btn_label_margin)
-            .set(ids.y_label, ui);
-    }
-
-    let (btn, mut m_text) = make_button(
-        app.state.r,
-        ids.frame,
-        options.button_active_colors.r,
-        options.button_inactive_colors.r,
-        options.background_color,
-        options.display_labels,
-        options.colored_rims,
-    );
-
-    btn.x_y_relative_to(ids.b_btn, 0., 42.).set(ids.r_btn, ui);
-
-    if let Some(text_color) = m_text.take() {
-        conrod_core::widget::Text::new("R")
-            .color(text_color)
-            .mid_top_with_margin_on(ids.r_btn, btn_label_margin)
-            .set(ids.r_label, ui);
-    }
-
-    let (btn, mut m_text) = make_button(
-        app.state.a,
-        ids.frame,
-        options.button_active_colors.a,
-        options.button_inactive_colors.a,
-        options.background_color,
-        options.display_labels,
-        options.colored_rims,
-    );
-
-    btn.x_y_relative_to(ids.b_btn, -10., -100.)
-        .set(ids.a_btn, ui);
-
-    if let Some(text_color) = m_text.take() {
-        conrod_core::widget::Text::new("A")
-            .color(text_color)
-            .mid_top_with_margin_on(ids.a_btn, btn_label_margin)
-            .set(ids.a_label, ui);
-    }
-
-    let (btn, mut m_text) = make_button(
-        app.state.c_up,
-        ids.frame,
-        options.button_active_colors.c_up,
-        options.button_inactive_colors.c_up,
-        options.background_color,
-        options.display_labels,
-        options.colored_rims,
-    );
-
-    btn.x_y_relative_to(ids.a_btn, 1., 48.)
-        .set(ids.c_up_btn, ui);
-
-    if let Some(text_color) = m_text.take() {
-        conrod_core::widget::Text::new("CU")
-            .color(text_color)
-            .mid_top_with_margin_on(ids.c_up_btn, btn_label_margin)
-            .set(ids.c_up_label, ui);
-    }
-
-    let (btn, mut m_text) = make_button(
-        app.state.c_left,
-        ids.frame,
-        options.button_active_colors.c_left,
-        options.button_inactive_colors.c_left,
-        options.background_color,
-        options.display_labels,
-        options.colored_rims,
-    );
-
-    btn.x_y_relative_to(ids.c_up_btn, -34., -24.)
-        .set(ids.c_left_btn, ui);
-
-    if let Some(text_color) = m_text.take() {
-        conrod_core::widget::Text::new("CL")
-            .color(text_color)
-            .mid_top_with_margin_on(ids.c_left_btn, btn_label_margin)
-            .set(ids.c_left_label, ui);
-    }
-
-    let (btn, mut m_text) = make_button(
-        app.state.c_right,
-        ids.frame,
-        options.button_active_colors.c_right,
-        options.button_inactive_colors.c_right,
-        options.background_color,
-        options.display_labels,
-        options.colored_rims,
-    );
-
-    btn.x_y_relative_to(ids.c_up_btn, 34., -24.)
-        .set(ids.c_right_btn, ui);
-
-    if let Some(text_color) = m_text.take() {
-        conrod_core::widget::Text::new("CR")
-            .color(text_color)
-            .mid_top_with_margin_on(ids.c_right_btn, btn_label_margin)
-            .set(ids.c_right_label, ui);
-    }
-
-    let (btn, mut m_text) = make_button(
-        app.state.c_down,
-        ids.frame,
-        options.button_active_colors.c_down,
-        options.button_inactive_colors.c_down,
-        options.background_color,
-        options.display_labels,
-        options.colored_rims,
-    );
-
-    btn.x_y_relative_to(ids.c_left_btn, 0., -48.)
-        .set(ids.c_down_btn, ui);
-
-    if let Some(text_color) = m_text.take() {
-        conrod_core::widget::Text::new("CD")
-            .color(text_color)
-            .mid_top_with_margin_on(ids.c_down_btn, btn_label_margin)
-            .set(ids.c_down_label, ui);
-    }
+    let button_count = if options.effective_model(app.detected_model) == B0xxModel::R2 {
+        BUTTON_POSITIONS.len()
+    } else {
+        R1_BUTTON_COUNT
+    };
 
-    if options.is_r2_b0xx {
-        let (btn, mut m_text) = make_button(
-            app.state.mod_ls,
-            ids.frame,
-            options.button_active_colors.mod_ls,
-            options.button_inactive_colors.mod_ls,
-            options.background_color,
-            options.display_labels,
-            options.colored_rims,
-        );
-
-        btn.x_y_relative_to(ids.y_btn, 43., -5.)
-            .set(ids.mod_ls_btn, ui);
-
-        if let Some(text_color) = m_text.take() {
-            conrod_core::widget::Text::new("LS")
-                .color(text_color)
-                .mid_top_with_margin_on(ids.mod_ls_btn, btn_label_margin)
-                .set(ids.mod_ls_label, ui);
+    let pressed = app.state.to_array();
+    let active_colors = options.button_active_colors.to_array();
+    let inactive_colors = options.button_inactive_colors.to_array();
+    let labels = options.labels.to_array();
+    let animation = &options.animation;
+    let now = std::time::Instant::now();
+
+    for i in 0..button_count {
+        let [x, y] = BUTTON_POSITIONS[i];
+        let image = if pressed[i] {
+            skin.pressed[i]
+        } else {
+            skin.released[i]
+        };
+
+        let shape = options.button_shape(BUTTON_NAMES[i]);
+        let [w, h] = shape.dimensions(BTN_RADIUS);
+
+        let fade = app.animations.progress(i, animation.fade_out, now);
+        let pulse = app.animations.progress(i, animation.press_pulse, now);
+        let glow = app.animations.progress(i, animation.glow, now);
+        let grow = if pressed[i] {
+            1. + animation.pulse_scale * (std::f64::consts::PI * pulse).sin()
+        } else {
+            1.
+        };
+
+        let glow = if pressed[i] { glow } else { 1. - glow };
+        if animation.glow > 0 && glow > 0. {
+            make_button(
+                ui,
+                ids.glows[i],
+                ids.frame,
+                shape,
+                active_colors[i].with_opacity(GLOW_OPACITY * glow),
+                None,
+                [x, y],
+                (scale_x, scale_y),
+                grow + 2. * animation.glow_size / w.min(h),
+            );
         }
 
-        let (btn, mut m_text) = make_button(
-            app.state.mod_ms,
-            ids.frame,
-            options.button_active_colors.mod_ms,
-            options.button_inactive_colors.mod_ms,
-            options.background_color,
-            options.display_labels,
-            options.colored_rims,
-        );
-
-        btn.x_y_relative_to(ids.mod_ls_btn, 40., -17.)
-            .set(ids.mod_ms_btn, ui);
-
-        if let Some(text_color) = m_text.take() {
-            conrod_core::widget::Text::new("MS")
-                .color(text_color)
-                .mid_top_with_margin_on(ids.mod_ms_btn, btn_label_margin)
-                .set(ids.mod_ms_label, ui);
+        // Released buttons fade from their active color when `fade_out` is set
+        let color = if pressed[i] {
+            active_colors[i]
+        } else {
+            active_colors[i].mix(inactive_colors[i], fade)
+        };
+
+        // Skin images replace the shape, labels then pick a text color readable on the button's color
+        let (btn_id, m_text) = if let Some(image) = image {
+            widget::Image::new(image)
+                .w_h(w * scale_x * grow, h * scale_y * grow)
+                .x_y_relative_to(ids.frame, x * scale_x, y * scale_y)
+                .parent(ids.frame)
+                .graphics_for(ids.frame)
+                .set(ids.button_images[i], ui);
+
+            let text_color = options
+                .display_labels
+                .then(|| color.over(options.background_color).readable_text_color().into());
+            (ids.button_images[i], text_color)
+        } else {
+            // With colored rims, released buttons are only outlined once they faded out
+            let rim = (options.colored_rims && !pressed[i] && fade >= 1.)
+                .then_some(inactive_colors[i]);
+            let fill = if pressed[i] || !options.colored_rims {
+                color
+            } else {
+                active_colors[i].mix(options.background_color, fade)
+            };
+            make_button(
+                ui,
+                ids.buttons[i],
+                ids.frame,
+                shape,
+                fill,
+                rim,
+                [x, y],
+                (scale_x, scale_y),
+                grow,
+            );
+
+            let text_color = options
+                .display_labels
+                .then(|| fill.over(options.background_color).readable_text_color().into());
+            (ids.buttons[i], text_color)
+        };
+
+        if let Some(inside_color) = m_text {
+            let size = options
+                .font
+                .label_size(BUTTON_NAMES[i], ui.theme().font_size_small);
+            // Labels outside of the buttons are read against the background
+            let outside_color = options.background_color.readable_text_color().into();
+
+            let label = conrod_core::widget::Text::new(labels[i]).font_size(font_size(size));
+            let label = match options.label_position(BUTTON_NAMES[i]) {
+                LabelPosition::Inside => {
+                    let margin = (h / 2. - size as f64).max(0.) * scale_y;
+                    label
+                        .color(inside_color)
+                        .mid_top_with_margin_on(btn_id, margin)
+                }
+                LabelPosition::Above => label
+                    .color(outside_color)
+                    .up_from(btn_id, LABEL_GAP * scale_y)
+                    .align_middle_x_of(btn_id),
+                LabelPosition::Below => label
+                    .color(outside_color)
+                    .down_from(btn_id, LABEL_GAP * scale_y)
+                    .align_middle_x_of(btn_id),
+                LabelPosition::Hidden => continue,
+            };
+
+            match label_font {
+                Some(font) => label.font_id(font).set(ids.labels[i], ui),
+                None => label.set(ids.labels[i], ui),
+            }
         }
     }
 
@@ -479,50 +294,111 @@ fn fps_counter(ui: &mut conrod_core::UiCell, ids: &Ids, app: &mut ViewerApp) {
         .set(ids.fps_counter, ui);
 }
 
-#[inline(always)]
-fn make_button(
-    pressed: bool,
-    parent: conrod_core::widget::Id,
-    active_color: crate::config::ViewerColor,
-    inactive_color: crate::config::ViewerColor,
-    background_color: crate::config::ViewerColor,
-    display_labels: bool,
-    colored_rims: bool,
-) -> (
-    conrod_core::widget::Oval<conrod_core::widget::primitive::shape::oval::Full>,
-    Option<conrod_core::Color>,
-) {
-    use conrod_core::{widget, Sizeable, Widget};
-
-    let color = if pressed {
-        active_color
-    } else if colored_rims {
-        background_color
-    } else {
-        inactive_color
-    };
-    let text_color = if display_labels {
-        let tmp: conrod_core::Color = color.clone().into();
-        Some(tmp.plain_contrast())
-    } else {
-        None
+/// Center of the box around `points`
+fn polygon_center(points: &[[f64; 2]]) -> [f64; 2] {
+    let middle = |axis: usize| {
+        let values = points.iter().map(|point| point[axis]);
+        (values.clone().fold(f64::MIN, f64::max) + values.fold(f64::MAX, f64::min)) / 2.
     };
+    [middle(0), middle(1)]
+}
 
-    let mut widget = if colored_rims && !pressed {
-        widget::Circle::outline_styled(
-            BTN_RADIUS,
-            widget::primitive::line::Style::solid()
-                .color(inactive_color.into())
-                .thickness(2.)
-        )
-    } else {
-        widget::Circle::fill_with(BTN_RADIUS, color.into())
-    };
+fn scale_points(points: &[[f64; 2]], (scale_x, scale_y): (f64, f64)) -> Vec<[f64; 2]> {
+    points
+        .iter()
+        .map(|[x, y]| [x * scale_x, y * scale_y])
+        .collect()
+}
 
-    widget = widget
+/// Draws a button centered on `[x, y]` in layout units, filled with `fill` or, when `rim` is set,
+/// only outlined with it. `grow` enlarges the shape around its center
+#[allow(clippy::too_many_arguments)]
+fn make_button(
+    ui: &mut conrod_core::UiCell,
+    id: conrod_core::widget::Id,
+    parent: conrod_core::widget::Id,
+    shape: &ButtonShape,
+    fill: crate::config::ViewerColor,
+    rim: Option<crate::config::ViewerColor>,
+    [x, y]: [f64; 2],
+    (scale_x, scale_y): (f64, f64),
+    grow: f64,
+) {
+    use conrod_core::{widget, Positionable, Widget};
+
+    fn place<W: Widget>(
+        widget: W,
+        ui: &mut conrod_core::UiCell,
+        id: widget::Id,
+        parent: widget::Id,
+        [x, y]: [f64; 2],
+    ) {
+        widget
+            .x_y_relative_to(parent, x, y)
             .parent(parent)
             .graphics_for(parent)
-            .w_h(BTN_RADIUS, BTN_RADIUS);
+            .set(id, ui);
+    }
 
-    (widget, text_color)
+    let [w, h] = shape.dimensions(BTN_RADIUS);
+    let dim = [w * scale_x * grow, h * scale_y * grow];
+    let xy = [x * scale_x, y * scale_y];
+    let fill = fill.into();
+    let rim = rim.map(|color| {
+        widget::primitive::line::Style::solid()
+            .color(color.into())
+            .thickness(2. * scale_x.min(scale_y))
+    });
+
+    match shape.kind {
+        ShapeKind::Circle | ShapeKind::Ellipse => match rim {
+            Some(rim) => place(widget::Oval::outline_styled(dim, rim), ui, id, parent, xy),
+            None => place(widget::Oval::fill_with(dim, fill), ui, id, parent, xy),
+        },
+        ShapeKind::Square => match rim {
+            Some(rim) => place(widget::Rectangle::outline_styled(dim, rim), ui, id, parent, xy),
+            None => place(widget::Rectangle::fill_with(dim, fill), ui, id, parent, xy),
+        },
+        ShapeKind::RoundedRectangle => {
+            let radius = shape.corner_radius(BTN_RADIUS) * scale_x.min(scale_y) * grow;
+            match rim {
+                Some(rim) => place(
+                    widget::RoundedRectangle::outline_styled(dim, radius, rim),
+                    ui,
+                    id,
+                    parent,
+                    xy,
+                ),
+                None => place(
+                    widget::RoundedRectangle::fill_with(dim, radius, fill),
+                    ui,
+                    id,
+                    parent,
+                    xy,
+                ),
+            }
+        }
+        ShapeKind::Polygon => {
+            // Centred polygons sit on the middle of their box, which may not be the button's center
+            let [cx, cy] = polygon_center(&shape.points);
+            let xy = [(x + cx) * scale_x, (y + cy) * scale_y];
+            let points = scale_points(&shape.points, (scale_x * grow, scale_y * grow));
+            match rim {
+                Some(rim) => place(
+                    widget::Polygon::centred_outline_styled(points, rim),
+                    ui,
+                    id,
+                    parent,
+                    xy,
+                ),
+                None => place(
+                    widget::Polygon::centred_fill_with(points, fill),
+                    ui,
+                    id,
+                    parent,
+                    xy,
+                ),
+            }
+        }
+    }
 }
@@ -1,10 +1,13 @@
+mod animation;
 mod app;
+mod fonts;
 mod gui;
+mod skin;
 mod support;
 
-use self::{app::*, support::*};
+use self::{app::*, fonts::*, skin::*, support::*};
 
-use crate::{config::ViewerOptions, serial_probe::*};
+use crate::{config::{watch_file, LayeredConfig, ViewerOptions, BUTTON_NAMES, MIN_SCALE}, serial_probe::*};
 
 use conrod_core::widget_ids;
 use glium::{self, Surface, glutin::event::ModifiersState};
@@ -24,63 +27,38 @@ widget_ids! {
         frame,
         reconnect_bg,
         reconnect_label,
-        start_btn,
-        y_btn,
-        x_btn,
-        b_btn,
-        a_btn,
-        l_btn,
-        r_btn,
-        z_btn,
-        up_btn,
-        down_btn,
-        right_btn,
-        left_btn,
-        mod_x_btn,
-        mod_y_btn,
-        mod_ls_btn,
-        mod_ms_btn,
-        c_left_btn,
-        c_right_btn,
-        c_up_btn,
-        c_down_btn,
-        start_label,
-        y_label,
-        x_label,
-        b_label,
-        a_label,
-        l_label,
-        r_label,
-        z_label,
-        up_label,
-        down_label,
-        right_label,
-        left_label,
-        mod_x_label,
-        mod_y_label,
-        mod_ls_label,
-        mod_ms_label,
-        c_left_label,
-        c_right_label,
-        c_up_label,
-        c_down_label,
+        background_image,
+        case_fill,
+        case_outline,
+        glows[],
+        buttons[],
+        button_images[],
+        labels[],
         fps_counter,
     }
 }
 
-pub fn start_gui(mut rx: crossbeam_channel::Receiver<B0xxMessage>, options: ViewerOptions) {
+pub fn start_gui(
+    mut rx: crossbeam_channel::Receiver<B0xxMessage>,
+    probe_options: ProbeOptions,
+    mut config: LayeredConfig,
+    mut options: ViewerOptions,
+) {
     // Build the window.
     let mut events_loop = glium::glutin::event_loop::EventLoop::new();
 
+    // Sizes are logical, the window gets sharper rather than smaller on HiDPI screens
     let window = glium::glutin::window::WindowBuilder::new()
         .with_decorations(!options.chromeless)
         .with_title(WIN_TITLE)
-        .with_resizable(false)
-        .with_inner_size::<glium::glutin::dpi::LogicalSize<u32>>((WIN_W, WIN_H).into());
+        .with_resizable(true)
+        .with_transparent(options.transparent)
+        .with_min_inner_size(window_size(MIN_SCALE))
+        .with_inner_size(window_size(options.window_scale()));
 
     let context = glium::glutin::ContextBuilder::new()
         .with_vsync(true)
-        .with_gl_robustness(if cfg!(profile = "release") {
+        .with_gl_robustness(if cfg!(not(debug_assertions)) {
             glium::glutin::Robustness::NoError
         } else {
             glium::glutin::Robustness::TryRobustLoseContextOnReset
@@ -91,7 +69,8 @@ pub fn start_gui(mut rx: crossbeam_channel::Receiver<B0xxMessage>, options: View
     let display = GliumDisplayWinitWrapper(display);
 
     // Construct our `Ui`.
-    let mut ui = conrod_core::UiBuilder::new([WIN_W as f64, WIN_H as f64])
+    let initial_size = window_size(options.window_scale());
+    let mut ui = conrod_core::UiBuilder::new([initial_size.width, initial_size.height])
         .theme(gui::theme())
         .build();
 
@@ -103,48 +82,80 @@ pub fn start_gui(mut rx: crossbeam_channel::Receiver<B0xxMessage>, options: View
 
     ui.theme.font_id = Some(alata_font);
 
-    let ids = Ids::new(ui.widget_id_generator());
+    let mut ids = Ids::new(ui.widget_id_generator());
+    ids.glows.resize(BUTTON_NAMES.len(), &mut ui.widget_id_generator());
+    ids.buttons.resize(BUTTON_NAMES.len(), &mut ui.widget_id_generator());
+    ids.button_images.resize(BUTTON_NAMES.len(), &mut ui.widget_id_generator());
+    ids.labels.resize(BUTTON_NAMES.len(), &mut ui.widget_id_generator());
 
-    let image_map: conrod_core::image::Map<glium::texture::CompressedSrgbTexture2d> =
-        conrod_core::image::Map::new();
+    let mut image_map = ImageMap::new();
+    let mut skin = SkinImages::load(&display.0, &mut image_map, &options);
+    let mut font_cache = FontCache::default();
+    let mut label_font = font_cache.label_font(&mut ui.fonts, &options);
 
     let mut app = ViewerApp::default();
 
     let mut renderer = Renderer::new(&display).unwrap();
 
     let (glutin_tx, glutin_rx) = crossbeam_channel::bounded::<()>(1);
+    let mut profile_hotkey = None;
+    let mut window_resized = false;
+    let mut animating = false;
+    let mut reconnection = None;
+
+    // Apply configuration changes live, without dropping the serial connection or the window
+    let config_watch = config.file.as_ref().and_then(|(path, _)| {
+        watch_file(path.clone())
+            .map_err(|e| warn!("Could not watch {} for changes: {}", path.display(), e))
+            .ok()
+    });
 
     'main: loop {
-        // Reconnect to the device if needed
+        // Reconnect to the device if needed, without blocking the window meanwhile
         if app.status == ViewerAppStatus::NeedsReconnection {
             app.status = ViewerAppStatus::Reconnecting;
+            app.disconnected();
             debug!("Trying to reconnect...");
-            drop(rx);
-            rx = reconnect(&options.custom_tty);
+            rx = crossbeam_channel::never();
+            reconnection = Some(reconnect(probe_options.clone()));
+            ui.handle_event(conrod_core::event::Input::Redraw);
+        }
+
+        if let Some(new_rx) = reconnection.as_ref().and_then(|pending| pending.try_recv().ok()) {
             debug!("Reconnected successfully!");
+            rx = new_rx;
+            reconnection = None;
         }
 
-        let mut maybe_state = match rx.iter().next() {
-            Some(message) => match message {
+        // Frames are paced by a clock as well as by reports, so the window stays responsive
+        // when the device is silent or gone
+        let message = rx.recv_timeout(FRAME_INTERVAL).ok();
+
+        // Reports may have queued up while the UI was busy, only the most recent state is drawn
+        let mut maybe_state = None;
+        for message in message.into_iter().chain(rx.try_iter()) {
+            match message {
                 B0xxMessage::State(state) => {
                     app.status.set_running();
-                    Some(state)
+                    maybe_state = Some(state);
+                }
+                B0xxMessage::Model(model) => {
+                    if app.set_detected_model(model) {
+                        ui.handle_event(conrod_core::event::Input::Redraw);
+                    }
                 }
                 B0xxMessage::Error(e) => {
                     error!("{}", e);
                     app.status = ViewerAppStatus::NeedsReconnection;
-                    None
                 }
                 B0xxMessage::Quit => {
                     break 'main;
                 }
                 B0xxMessage::Reconnect => {
                     app.status = ViewerAppStatus::NeedsReconnection;
-                    None
                 }
-            },
-            None => None,
-        };
+            }
+        }
 
         // Redraw our window contents only and only if the state of inputs have
         // changed in the current cached report
@@ -171,6 +182,26 @@ pub fn start_gui(mut rx: crossbeam_channel::Receiver<B0xxMessage>, options: View
                     } => {
                         let _ = glutin_tx.send(());
                     },
+                    glium::glutin::event::WindowEvent::KeyboardInput {
+                        input:
+                            glium::glutin::event::KeyboardInput {
+                                state: glium::glutin::event::ElementState::Pressed,
+                                virtual_keycode: Some(key),
+                                ..
+                            },
+                        ..
+                    } => {
+                        if let Some(hotkey) = ProfileHotkey::from_key(key) {
+                            profile_hotkey = Some(hotkey);
+                        }
+                    },
+                    glium::glutin::event::WindowEvent::Resized(size) => {
+                        display.0.gl_window().resize(size);
+                        window_resized = true;
+                    },
+                    glium::glutin::event::WindowEvent::ScaleFactorChanged { .. } => {
+                        window_resized = true;
+                    },
                     // If ALT is held, allow the window to be click-dragged
                     glium::glutin::event::WindowEvent::ModifiersChanged(modifiers) => {
                         if modifiers.contains(ModifiersState::ALT) {
@@ -212,18 +243,67 @@ pub fn start_gui(mut rx: crossbeam_channel::Receiver<B0xxMessage>, options: View
             *control_flow = glium::glutin::event_loop::ControlFlow::Exit;
         });
 
-        if let Ok(_) = glutin_rx.try_recv() {
+        if glutin_rx.try_recv().is_ok() {
             break 'main;
         }
 
+        let new_options = profile_hotkey
+            .take()
+            .and_then(|hotkey| switch_profile(&mut config, hotkey))
+            .or_else(|| match &config_watch {
+                Some(watch) if watch.try_recv().is_ok() => reload_config(&mut config, &options),
+                _ => None,
+            });
+
+        if let Some(new_options) = new_options {
+            display.0
+                .gl_window()
+                .window()
+                .set_decorations(!new_options.chromeless);
+            if new_options.transparent != options.transparent {
+                warn!("Switching the window transparency takes effect after a restart");
+            }
+            if new_options.window_scale() != options.window_scale() {
+                display.0
+                    .gl_window()
+                    .window()
+                    .set_inner_size(window_size(new_options.window_scale()));
+            }
+            // Images are read again too, in case they were edited
+            skin.unload(&mut image_map);
+            skin = SkinImages::load(&display.0, &mut image_map, &new_options);
+            label_font = font_cache.label_font(&mut ui.fonts, &new_options);
+            options = new_options;
+            ui.handle_event(conrod_core::event::Input::Redraw);
+        }
+
+        // Lay the viewer out again for the new logical size of the window
+        if std::mem::take(&mut window_resized) {
+            let window = display.0.gl_window();
+            let size = window
+                .window()
+                .inner_size()
+                .to_logical::<f64>(window.window().scale_factor());
+            ui.handle_event(conrod_core::event::Input::Resize(size.width, size.height));
+        }
+
+        // One more frame once the transitions end, so they settle on their final state
+        let was_animating = std::mem::replace(
+            &mut animating,
+            app.animations.is_running(&options.animation, std::time::Instant::now()),
+        );
+        if animating || was_animating {
+            ui.handle_event(conrod_core::event::Input::Redraw);
+        }
+
         // Instantiate the b0xx viewer GUI
-        gui::render_gui(&mut ui.set_widgets(), &ids, &mut app, &options);
+        gui::render_gui(&mut ui.set_widgets(), &ids, &mut app, &options, &skin, label_font);
 
         // Draw the `Ui`.
         if let Some(primitives) = ui.draw_if_changed() {
             renderer.fill(&display.0, primitives, &image_map);
             let mut frame = display.0.draw();
-            frame.clear_color(0., 0., 0., 1.);
+            frame.clear_color(0., 0., 0., if options.transparent { 0. } else { 1. });
             renderer.draw(&display.0, &mut frame, &image_map).unwrap();
             frame.finish().unwrap();
         }
@@ -0,0 +1,72 @@
+use crate::config::{ViewerOptions, BUTTON_NAMES};
+use crate::error::ViewerError;
+
+pub type ImageMap = conrod_core::image::Map<glium::texture::SrgbTexture2d>;
+
+/// The images of `ViewerOptions::skin` once uploaded to the GPU, buttons are in `BUTTON_NAMES` order
+#[derive(Debug, Default)]
+pub struct SkinImages {
+    pub background: Option<conrod_core::image::Id>,
+    pub pressed: [Option<conrod_core::image::Id>; 20],
+    pub released: [Option<conrod_core::image::Id>; 20],
+}
+
+fn load_texture(
+    display: &glium::Display,
+    path: &std::path::Path,
+) -> Result<glium::texture::SrgbTexture2d, ViewerError> {
+    let image = image::open(path)?.to_rgba8();
+    let dimensions = image.dimensions();
+    let raw = glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), dimensions);
+    glium::texture::SrgbTexture2d::new(display, raw).map_err(|e| anyhow::anyhow!("{}", e).into())
+}
+
+impl SkinImages {
+    /// Reads every image of the skin. Images that can't be loaded are reported and left out,
+    /// the flat background or circle is drawn instead
+    pub fn load(
+        display: &glium::Display,
+        image_map: &mut ImageMap,
+        options: &ViewerOptions,
+    ) -> Self {
+        let mut loaded = std::collections::HashMap::new();
+        let mut load = |path: Option<&std::path::Path>| {
+            let path = options.asset_path(path?);
+            *loaded
+                .entry(path.clone())
+                .or_insert_with(|| match load_texture(display, &path) {
+                    Ok(texture) => Some(image_map.insert(texture)),
+                    Err(e) => {
+                        warn!("Could not load skin image {}: {}", path.display(), e);
+                        None
+                    }
+                })
+        };
+
+        let mut skin = Self {
+            background: load(options.skin.background.as_deref()),
+            ..Default::default()
+        };
+
+        for (i, name) in BUTTON_NAMES.iter().enumerate() {
+            let (pressed, released) = options.skin.button(name);
+            skin.pressed[i] = load(pressed);
+            skin.released[i] = load(released);
+        }
+
+        skin
+    }
+
+    /// Frees the textures, before loading the skin of new options
+    pub fn unload(self, image_map: &mut ImageMap) {
+        let ids = self
+            .background
+            .into_iter()
+            .chain(self.pressed.into_iter().flatten())
+            .chain(self.released.into_iter().flatten());
+
+        for id in ids {
+            image_map.remove(id);
+        }
+    }
+}
@@ -2,6 +2,15 @@ pub const WIN_W: u32 = 600;
 pub const WIN_H: u32 = 300;
 pub const BTN_RADIUS: f64 = 40.;
 
+/// Longest wait for a report before window events, configuration changes and animations are
+/// handled anyway
+pub const FRAME_INTERVAL: std::time::Duration = std::time::Duration::from_millis(16);
+
+/// Logical size of the window for the given layout scale
+pub fn window_size(scale: f64) -> glium::glutin::dpi::LogicalSize<f64> {
+    glium::glutin::dpi::LogicalSize::new(WIN_W as f64 * scale, WIN_H as f64 * scale)
+}
+
 #[cfg(not(feature = "fake_serial"))]
 pub const WIN_TITLE: &str = "B0XX Input Viewer - by @OtaK_";
 